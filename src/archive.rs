@@ -0,0 +1,592 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use iced::futures::channel::mpsc;
+use iced::futures::SinkExt;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::{Error, FileType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+}
+
+const SUFFIXES: [(&str, ArchiveKind); 6] = [
+    (".zip", ArchiveKind::Zip),
+    (".tar", ArchiveKind::Tar),
+    (".tar.gz", ArchiveKind::TarGz),
+    (".tgz", ArchiveKind::TarGz),
+    (".tar.xz", ArchiveKind::TarXz),
+    (".txz", ArchiveKind::TarXz),
+];
+
+pub fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    SUFFIXES
+        .iter()
+        .find(|(suffix, _)| name.len() > suffix.len() && name.ends_with(suffix))
+        .map(|(_, kind)| *kind)
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    archive_kind(path).is_some()
+}
+
+// "release.tar.gz" -> "release", used as the folder name when extracting
+pub fn archive_stem(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "archive".to_string());
+    let lower = name.to_lowercase();
+    SUFFIXES
+        .iter()
+        .filter(|(suffix, _)| lower.ends_with(suffix))
+        .map(|(suffix, _)| name[..name.len() - suffix.len()].to_string())
+        .min_by_key(|stem| stem.len())
+        .unwrap_or(name)
+}
+
+// Splits "/x/a.zip/src/main.rs" into the archive on disk and the entry path inside it
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, String)> {
+    for archive in path.ancestors() {
+        if is_archive(archive) && archive.is_file() {
+            let inner = path
+                .strip_prefix(archive)
+                .ok()?
+                .components()
+                .filter_map(|part| match part {
+                    Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            return Some((archive.to_path_buf(), inner));
+        }
+    }
+    None
+}
+
+fn io_error(error: io::Error) -> Error {
+    Error::IOErr(error.kind())
+}
+
+fn zip_error(error: ZipError) -> Error {
+    match error {
+        ZipError::Io(error) => Error::IOErr(error.kind()),
+        ZipError::FileNotFound => Error::IOErr(io::ErrorKind::NotFound),
+        _ => Error::IOErr(io::ErrorKind::InvalidData),
+    }
+}
+
+fn open_zip(archive: &Path) -> Result<ZipArchive<File>, Error> {
+    let file = File::open(archive).map_err(io_error)?;
+    ZipArchive::new(file).map_err(zip_error)
+}
+
+// Tar archives can only be read front to back, so every operation opens a fresh reader
+fn open_tar(archive: &Path) -> Result<tar::Archive<Box<dyn Read>>, Error> {
+    let file = BufReader::new(File::open(archive).map_err(io_error)?);
+    let reader: Box<dyn Read> = match archive_kind(archive) {
+        Some(ArchiveKind::TarGz) => Box::new(flate2::read::GzDecoder::new(file)),
+        Some(ArchiveKind::TarXz) => Box::new(xz2::read::XzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    let mut tar = tar::Archive::new(reader);
+    tar.set_preserve_permissions(true);
+    tar.set_preserve_mtime(true);
+    tar.set_overwrite(true);
+    Ok(tar)
+}
+
+fn tar_name(entry: &tar::Entry<'_, Box<dyn Read>>) -> Result<String, Error> {
+    let path = entry.path().map_err(io_error)?;
+    let name = path
+        .components()
+        .filter_map(|part| match part {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    Ok(name)
+}
+
+async fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|_| Error::IOErr(io::ErrorKind::Other))?
+}
+
+fn prefix_of(inner: &str) -> String {
+    if inner.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", inner.trim_end_matches('/'))
+    }
+}
+
+// Every entry name in the archive, folders end with '/'
+fn entry_names(archive: &Path) -> Result<Vec<String>, Error> {
+    if archive_kind(archive) == Some(ArchiveKind::Zip) {
+        let zip = open_zip(archive)?;
+        return Ok(zip.file_names().map(str::to_string).collect());
+    }
+    let mut tar = open_tar(archive)?;
+    let mut names = Vec::new();
+    for entry in tar.entries().map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        let mut name = tar_name(&entry)?;
+        if entry.header().entry_type().is_dir() {
+            name.push('/');
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+// Archives do not always store folder entries, so folders are worked out from the entry names
+pub async fn read_archive_directory(archive: PathBuf, inner: String) -> Result<Vec<FileType>, Error> {
+    blocking(move || {
+        let prefix = prefix_of(&inner);
+        let mut children = BTreeMap::new();
+        let mut found = inner.is_empty();
+        for name in entry_names(&archive)? {
+            let Some(rest) = name.strip_prefix(&prefix) else {
+                continue;
+            };
+            found = true;
+            let rest = rest.trim_start_matches('/');
+            if rest.is_empty() {
+                continue;
+            }
+            match rest.split_once('/') {
+                Some((child, _)) => {
+                    children.insert(child.to_string(), true);
+                }
+                None => {
+                    children.entry(rest.to_string()).or_insert(false);
+                }
+            }
+        }
+        if !found {
+            return Err(Error::IOErr(io::ErrorKind::NotFound));
+        }
+        Ok(children
+            .into_iter()
+            .map(|(name, is_dir)| if is_dir { FileType::Dir(name) } else { FileType::File(name) })
+            .collect())
+    })
+    .await
+}
+
+pub async fn read_archive_file(archive: PathBuf, inner: String) -> Result<Arc<String>, Error> {
+    blocking(move || {
+        let mut content = String::new();
+        if archive_kind(&archive) == Some(ArchiveKind::Zip) {
+            let mut zip = open_zip(&archive)?;
+            let mut entry = zip.by_name(&inner).map_err(zip_error)?;
+            entry.read_to_string(&mut content).map_err(io_error)?;
+            return Ok(Arc::new(content));
+        }
+        let mut tar = open_tar(&archive)?;
+        for entry in tar.entries().map_err(io_error)? {
+            let mut entry = entry.map_err(io_error)?;
+            if tar_name(&entry)? == inner {
+                entry.read_to_string(&mut content).map_err(io_error)?;
+                return Ok(Arc::new(content));
+            }
+        }
+        Err(Error::IOErr(io::ErrorKind::NotFound))
+    })
+    .await
+}
+
+// Copies a file or a whole folder out of the archive into destination, the same way
+// copy_file and copy_foldder place things inside the destination folder
+pub async fn extract_entry(
+    archive: PathBuf,
+    inner: String,
+    destination: PathBuf,
+) -> Result<PathBuf, Error> {
+    blocking(move || {
+        let names = entry_names(&archive)?;
+        if names.contains(&inner) {
+            let name = inner.rsplit('/').next().unwrap_or(&inner);
+            let dest_path = destination.join(name);
+            unpack_matching(&archive, &destination, |entry| {
+                (entry == inner).then(|| dest_path.clone())
+            })?;
+            return Ok(dest_path);
+        }
+
+        let root_name = match inner.rsplit('/').next() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => archive_stem(&archive),
+        };
+        let dest_path = destination.join(root_name);
+        std::fs::create_dir_all(&dest_path).map_err(io_error)?;
+        let prefix = prefix_of(&inner);
+        unpack_matching(&archive, &destination, |entry| {
+            entry.strip_prefix(&prefix).map(|rest| dest_path.join(rest))
+        })?;
+        Ok(dest_path)
+    })
+    .await
+}
+
+// Unpacks every entry that target maps to a path on disk under root
+fn unpack_matching(
+    archive: &Path,
+    root: &Path,
+    target: impl Fn(&str) -> Option<PathBuf>,
+) -> Result<(), Error> {
+    if archive_kind(archive) == Some(ArchiveKind::Zip) {
+        let mut zip = open_zip(archive)?;
+        for index in 0..zip.len() {
+            let mut entry = zip.by_index(index).map_err(zip_error)?;
+            let Some(name) = entry.enclosed_name() else {
+                continue;
+            };
+            let name = name.to_string_lossy().replace('\\', "/");
+            if let Some(path) = target(&name) {
+                unpack_zip_entry(&mut entry, root, &path)?;
+            }
+        }
+        return Ok(());
+    }
+    let mut tar = open_tar(archive)?;
+    for entry in tar.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        let name = tar_name(&entry)?;
+        // A hard link names its target from the archive's root, which isn't where the
+        // entry is put, so it could point at any file on disk
+        if name.split('/').any(|part| part == "..")
+            || entry.header().entry_type() == tar::EntryType::Link
+        {
+            continue;
+        }
+        if let Some(path) = target(&name) {
+            not_through_links(root, &path)?;
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(io_error)?;
+            }
+            entry.unpack(&path).map_err(io_error)?;
+        }
+    }
+    Ok(())
+}
+
+// An archive can hold a symlink to anywhere and then an entry inside that link's folder.
+// Nothing is written where an earlier entry, or anything else, put a link on the way from
+// root to target, and a link at target itself is replaced rather than written through
+fn not_through_links(root: &Path, target: &Path) -> Result<(), Error> {
+    let relative = target
+        .strip_prefix(root)
+        .map_err(|_| Error::IOErr(io::ErrorKind::InvalidInput))?;
+    let mut path = root.to_path_buf();
+    let mut parts = relative.components().peekable();
+    while let Some(part) = parts.next() {
+        path.push(part);
+        let is_link = std::fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_symlink());
+        if !is_link {
+            continue;
+        }
+        if parts.peek().is_some() {
+            return Err(Error::IOErr(io::ErrorKind::InvalidData));
+        }
+        std::fs::remove_file(&path).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+fn unpack_zip_entry(
+    entry: &mut zip::read::ZipFile<'_>,
+    root: &Path,
+    target: &Path,
+) -> Result<(), Error> {
+    not_through_links(root, target)?;
+    if entry.is_dir() {
+        return std::fs::create_dir_all(target).map_err(io_error);
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    #[cfg(unix)]
+    if entry.is_symlink() {
+        let mut link = String::new();
+        entry.read_to_string(&mut link).map_err(io_error)?;
+        let _ = std::fs::remove_file(target);
+        return std::os::unix::fs::symlink(link, target).map_err(io_error);
+    }
+    let mut file = File::create(target).map_err(io_error)?;
+    io::copy(entry, &mut file).map_err(io_error)?;
+    #[cfg(unix)]
+    if let Some(mode) = entry.unix_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(target, std::fs::Permissions::from_mode(mode & 0o7777))
+            .map_err(io_error)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub enum Progress {
+    Entry { done: usize, total: usize },
+    Finished(Result<PathBuf, Error>),
+}
+
+// Extracts the whole archive into destination, restoring file modes and symlinks,
+// and reports after every entry. The last message is always Progress::Finished
+pub async fn extract_archive(
+    archive: PathBuf,
+    destination: PathBuf,
+    mut progress: mpsc::Sender<Progress>,
+) {
+    let mut sender = progress.clone();
+    let result = blocking(move || {
+        std::fs::create_dir_all(&destination).map_err(io_error)?;
+        let total = entry_names(&archive)?.len();
+        extract_all(&archive, &destination, |done| {
+            let _ = sender.try_send(Progress::Entry { done, total });
+        })?;
+        Ok(destination)
+    })
+    .await;
+    let _ = progress.send(Progress::Finished(result)).await;
+}
+
+fn extract_all(archive: &Path, destination: &Path, mut report: impl FnMut(usize)) -> Result<(), Error> {
+    let mut done = 0;
+    if archive_kind(archive) == Some(ArchiveKind::Zip) {
+        let mut zip = open_zip(archive)?;
+        for index in 0..zip.len() {
+            let mut entry = zip.by_index(index).map_err(zip_error)?;
+            if let Some(name) = entry.enclosed_name() {
+                unpack_zip_entry(&mut entry, destination, &destination.join(name))?;
+            }
+            done += 1;
+            report(done);
+        }
+        return Ok(());
+    }
+    let mut tar = open_tar(archive)?;
+    for entry in tar.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        // unpack_in refuses entries that would land outside destination
+        entry.unpack_in(destination).map_err(io_error)?;
+        done += 1;
+        report(done);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub const ALL: [ArchiveFormat; 2] = [ArchiveFormat::Zip, ArchiveFormat::TarGz];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+}
+
+impl std::fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveFormat::Zip => write!(f, "zip"),
+            ArchiveFormat::TarGz => write!(f, "tar.gz"),
+        }
+    }
+}
+
+// A file, folder or symlink to store, with its name inside the archive
+struct Source {
+    path: PathBuf,
+    name: String,
+    metadata: std::fs::Metadata,
+}
+
+// Symlinks are stored as links and never followed, so a looping link cannot recurse forever
+fn collect_sources(sources: &[PathBuf]) -> Result<Vec<Source>, Error> {
+    let mut collected = Vec::new();
+    let mut stack = std::collections::VecDeque::new();
+    for source in sources {
+        let name = source
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or(Error::IOErr(io::ErrorKind::InvalidInput))?;
+        stack.push_back((source.clone(), name));
+    }
+    while let Some((path, name)) = stack.pop_front() {
+        let metadata = std::fs::symlink_metadata(&path).map_err(io_error)?;
+        if metadata.is_dir() {
+            for entry in std::fs::read_dir(&path).map_err(io_error)? {
+                let entry = entry.map_err(io_error)?;
+                let child = format!("{}/{}", name, entry.file_name().to_string_lossy());
+                stack.push_back((entry.path(), child));
+            }
+        }
+        collected.push(Source { path, name, metadata });
+    }
+    Ok(collected)
+}
+
+// Writes sources into a new archive at destination, reporting after every entry.
+// The last message is always Progress::Finished
+pub async fn create_archive(
+    sources: Vec<PathBuf>,
+    destination: PathBuf,
+    format: ArchiveFormat,
+    level: u32,
+    mut progress: mpsc::Sender<Progress>,
+) {
+    let mut sender = progress.clone();
+    let result = blocking(move || {
+        let entries = collect_sources(&sources)?;
+        let total = entries.len();
+        let report = |done| {
+            let _ = sender.try_send(Progress::Entry { done, total });
+        };
+        let written = match format {
+            ArchiveFormat::Zip => write_zip(&entries, &destination, level, report),
+            ArchiveFormat::TarGz => write_tar_gz(&entries, &destination, level, report),
+        };
+        if written.is_err() {
+            let _ = std::fs::remove_file(&destination);
+        }
+        written.map(|_| destination)
+    })
+    .await;
+    let _ = progress.send(Progress::Finished(result)).await;
+}
+
+fn write_zip(
+    entries: &[Source],
+    destination: &Path,
+    level: u32,
+    mut report: impl FnMut(usize),
+) -> Result<(), Error> {
+    let file = File::create_new(destination).map_err(io_error)?;
+    let mut zip = zip::ZipWriter::new(io::BufWriter::new(file));
+    let method = if level == 0 {
+        zip::CompressionMethod::Stored
+    } else {
+        zip::CompressionMethod::Deflated
+    };
+    for (done, entry) in entries.iter().enumerate() {
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(method)
+            .compression_level((level > 0).then_some(level.min(9) as i64));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            options = options.unix_permissions(entry.metadata.permissions().mode() & 0o7777);
+        }
+        if let Some(modified) = entry.metadata.modified().ok().and_then(zip_time) {
+            options = options.last_modified_time(modified);
+        }
+        if entry.metadata.is_symlink() {
+            let target = std::fs::read_link(&entry.path).map_err(io_error)?;
+            zip.add_symlink(&entry.name, target.to_string_lossy(), options)
+                .map_err(zip_error)?;
+        } else if entry.metadata.is_dir() {
+            zip.add_directory(&entry.name, options).map_err(zip_error)?;
+        } else {
+            zip.start_file(&entry.name, options).map_err(zip_error)?;
+            let mut source = File::open(&entry.path).map_err(io_error)?;
+            io::copy(&mut source, &mut zip).map_err(io_error)?;
+        }
+        report(done + 1);
+    }
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+// Zip stores local calendar fields, this uses UTC the same way tar stores mtimes
+fn zip_time(time: std::time::SystemTime) -> Option<zip::DateTime> {
+    let (year, month, day, seconds) = calendar(time)?;
+    zip::DateTime::from_date_and_time(
+        year,
+        month,
+        day,
+        (seconds / 3600) as u8,
+        (seconds % 3600 / 60) as u8,
+        (seconds % 60) as u8,
+    )
+    .ok()
+}
+
+// The UTC year, month, day and seconds into that day
+pub fn calendar(time: std::time::SystemTime) -> Option<(u16, u8, u8, u32)> {
+    let seconds = time.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400 + i64::from(month <= 2)) as u16;
+    Some((year, month, day, rest as u32))
+}
+
+// How times are shown in lists, like "2024-05-01 13:45:07 UTC"
+pub fn time_label(time: std::time::SystemTime) -> String {
+    match calendar(time) {
+        Some((year, month, day, seconds)) => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        ),
+        None => "unknown time".to_string(),
+    }
+}
+
+fn write_tar_gz(
+    entries: &[Source],
+    destination: &Path,
+    level: u32,
+    mut report: impl FnMut(usize),
+) -> Result<(), Error> {
+    let file = File::create_new(destination).map_err(io_error)?;
+    let encoder = flate2::write::GzEncoder::new(
+        io::BufWriter::new(file),
+        flate2::Compression::new(level.min(9)),
+    );
+    let mut tar = tar::Builder::new(encoder);
+    tar.follow_symlinks(false);
+    for (done, entry) in entries.iter().enumerate() {
+        tar.append_path_with_name(&entry.path, &entry.name)
+            .map_err(io_error)?;
+        report(done + 1);
+    }
+    tar.into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(io_error)?;
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use std::fs::{File, Metadata, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+// Writes the whole file or leaves the old one as it was. The text goes to a temporary file
+// in the same folder, is flushed to disk and then renamed over the original, so a crash
+// mid-write never leaves half a file behind
+pub async fn write(path: PathBuf, contents: Vec<u8>) -> Result<(), Error> {
+    tokio::task::spawn_blocking(move || write_file(&path, &contents))
+        .await
+        .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+        .map_err(|error| Error::IOErr(error.kind()))
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    // Saving through a symlink changes the file it points to and keeps the link
+    let path = match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => std::fs::canonicalize(path)?,
+        _ => path.to_path_buf(),
+    };
+    let existing = match std::fs::metadata(&path) {
+        Ok(meta) => Some(meta),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => return Err(error),
+    };
+    if existing.as_ref().is_some_and(|meta| !replaceable(meta)) {
+        return write_in_place(&path, contents);
+    }
+    match replace(&path, contents, existing.as_ref()) {
+        Ok(()) => Ok(()),
+        // A folder we can't create files in, or a file that is open elsewhere on Windows
+        Err(error)
+            if matches!(
+                error.kind(),
+                io::ErrorKind::PermissionDenied
+                    | io::ErrorKind::ReadOnlyFilesystem
+                    | io::ErrorKind::Unsupported
+            ) =>
+        {
+            write_in_place(&path, contents)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+// Renaming over a file with other hard links would split it from them, so those and
+// anything that isn't a plain file are written in place
+fn replaceable(meta: &Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        meta.is_file() && meta.nlink() == 1
+    }
+    #[cfg(not(unix))]
+    meta.is_file()
+}
+
+fn replace(path: &Path, contents: &[u8], existing: Option<&Metadata>) -> io::Result<()> {
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = folder.join(format!(".{}.save-{}", name, uuid::Uuid::new_v4()));
+    let result = write_temp(&temp, contents, existing).and_then(|()| {
+        #[cfg(unix)]
+        if existing.is_some() {
+            copy_xattrs(path, &temp);
+        }
+        std::fs::rename(&temp, path)
+    });
+    if let Err(error) = result {
+        let _ = std::fs::remove_file(&temp);
+        return Err(error);
+    }
+    // The rename itself is only on disk once the folder is
+    #[cfg(unix)]
+    if let Ok(folder) = File::open(folder) {
+        let _ = folder.sync_all();
+    }
+    Ok(())
+}
+
+fn write_temp(temp: &Path, contents: &[u8], existing: Option<&Metadata>) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    file.write_all(contents)?;
+    if let Some(meta) = existing {
+        file.set_permissions(meta.permissions())?;
+        // Only root can give a file away, a save that would change its owner is written in
+        // place instead
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let created = file.metadata()?;
+            if (created.uid(), created.gid()) != (meta.uid(), meta.gid()) {
+                std::os::unix::fs::fchown(&file, Some(meta.uid()), Some(meta.gid()))
+                    .map_err(|_| io::Error::from(io::ErrorKind::PermissionDenied))?;
+            }
+        }
+    }
+    file.sync_all()
+}
+
+// Best effort, file systems without extended attributes or attributes only root may set
+// are skipped. Copies and saves both keep them this way
+#[cfg(unix)]
+pub fn copy_xattrs(from: &Path, to: &Path) {
+    if let Ok(names) = xattr::list(from) {
+        for name in names {
+            if let Ok(Some(value)) = xattr::get(from, &name) {
+                let _ = xattr::set(to, &name, &value);
+            }
+        }
+    }
+}
+
+// The fallback, the same as a plain write but flushed to disk before the save counts as done
+fn write_in_place(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
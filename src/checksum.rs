@@ -0,0 +1,322 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::Digest;
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha1,
+    Md5,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 4] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha1,
+        HashAlgorithm::Md5,
+        HashAlgorithm::Blake3,
+    ];
+
+    // The names the coreutils and b3sum tools give their manifests
+    pub fn manifest_name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "SHA256SUMS",
+            HashAlgorithm::Sha1 => "SHA1SUMS",
+            HashAlgorithm::Md5 => "MD5SUMS",
+            HashAlgorithm::Blake3 => "B3SUMS",
+        }
+    }
+
+    // SHA-256 and BLAKE3 hashes are the same length, so the manifest's name decides
+    // between them
+    fn detect(manifest: &Path, hash: &str) -> Option<HashAlgorithm> {
+        let name = manifest
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        match hash.len() {
+            32 => Some(HashAlgorithm::Md5),
+            40 => Some(HashAlgorithm::Sha1),
+            64 if name.contains("b3") || name.contains("blake3") => Some(HashAlgorithm::Blake3),
+            64 => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashAlgorithm::Sha256 => write!(f, "SHA-256"),
+            HashAlgorithm::Sha1 => write!(f, "SHA-1"),
+            HashAlgorithm::Md5 => write!(f, "MD5"),
+            HashAlgorithm::Blake3 => write!(f, "BLAKE3"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Missing,
+}
+
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub relative: String,
+    pub outcome: Outcome,
+}
+
+// The Checksums page, either the hashes of the selected files or a manifest check
+#[derive(Debug)]
+pub enum Checksums {
+    Hashes {
+        sources: Vec<PathBuf>,
+        running: bool,
+        hashes: Vec<(PathBuf, String)>,
+    },
+    Verify {
+        manifest: PathBuf,
+        running: bool,
+        results: Vec<Verification>,
+    },
+}
+
+impl Checksums {
+    pub fn running(&self) -> bool {
+        match self {
+            Checksums::Hashes { running, .. } | Checksums::Verify { running, .. } => *running,
+        }
+    }
+}
+
+// Lower case hex, as sha256sum prints it
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    match algorithm {
+        HashAlgorithm::Sha256 => digest_file::<sha2::Sha256>(&mut file),
+        HashAlgorithm::Sha1 => digest_file::<sha1::Sha1>(&mut file),
+        HashAlgorithm::Md5 => digest_file::<md5::Md5>(&mut file),
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            io::copy(&mut file, &mut hasher)?;
+            Ok(hasher.finalize().to_hex().to_string())
+        }
+    }
+}
+
+fn digest_file<D: Digest + Write>(file: &mut File) -> io::Result<String> {
+    let mut hasher = D::new();
+    io::copy(file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+pub async fn hash_files(
+    paths: Vec<PathBuf>,
+    algorithm: HashAlgorithm,
+) -> Result<Vec<(PathBuf, String)>, Error> {
+    tokio::task::spawn_blocking(move || {
+        paths
+            .into_iter()
+            .map(|path| {
+                let hash = hash_file(&path, algorithm)?;
+                Ok((path, hash))
+            })
+            .collect::<io::Result<Vec<_>>>()
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error| Error::IOErr(error.kind()))
+}
+
+// Hashes every file under the folder into a manifest inside it, one `hash  path` line per
+// file with / between folders. An older manifest of the same name is replaced, not listed
+pub async fn write_manifest(
+    folder: PathBuf,
+    algorithm: HashAlgorithm,
+) -> Result<(PathBuf, usize), Error> {
+    tokio::task::spawn_blocking(move || {
+        let manifest = folder.join(algorithm.manifest_name());
+        let mut files = Vec::new();
+        collect_files(&folder, "", &mut files)?;
+        files.retain(|relative| *relative != algorithm.manifest_name());
+        files.sort();
+        let mut text = String::new();
+        for relative in &files {
+            let hash = hash_file(&folder.join(relative), algorithm)?;
+            text.push_str(&manifest_line(&hash, relative));
+        }
+        std::fs::write(&manifest, text)?;
+        Ok((manifest, files.len()))
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+fn collect_files(dir: &Path, relative: &str, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let child = if relative.is_empty() {
+            name
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        if meta.is_dir() {
+            collect_files(&entry.path(), &child, files)?;
+        } else if meta.is_file() {
+            files.push(child);
+        }
+    }
+    Ok(())
+}
+
+// Like sha256sum, a name with a backslash or a line break in it is written escaped and the
+// line starts with a backslash to say so
+fn manifest_line(hash: &str, relative: &str) -> String {
+    if !relative.contains(['\\', '\n', '\r']) {
+        return format!("{}  {}\n", hash, relative);
+    }
+    let escaped = relative
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("\\{}  {}\n", hash, escaped)
+}
+
+// The other way round, None for an escape sha256sum doesn't write
+fn unescape(escaped: &str) -> Option<String> {
+    let mut name = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            name.push(c);
+            continue;
+        }
+        name.push(match chars.next()? {
+            '\\' => '\\',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(name)
+}
+
+// Checks every line of a sha256sum style manifest against the files next to it. Lines
+// that aren't `hash  path` or `hash *path` make the whole manifest invalid
+pub async fn verify_manifest(manifest: PathBuf) -> Result<Vec<Verification>, Error> {
+    tokio::task::spawn_blocking(move || {
+        let text = std::fs::read_to_string(&manifest)?;
+        let folder = manifest.parent().unwrap_or(Path::new("."));
+        let mut results = Vec::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let invalid = || io::Error::from(io::ErrorKind::InvalidData);
+            let (escaped, line) = match line.strip_prefix('\\') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let (hash, rest) = line.split_once(' ').ok_or_else(invalid)?;
+            let relative = rest
+                .strip_prefix(' ')
+                .or_else(|| rest.strip_prefix('*'))
+                .ok_or_else(invalid)?;
+            let relative = if escaped {
+                unescape(relative).ok_or_else(invalid)?
+            } else {
+                relative.to_string()
+            };
+            let algorithm = HashAlgorithm::detect(&manifest, hash).ok_or_else(invalid)?;
+            let outcome = match hash_file(&folder.join(&relative), algorithm) {
+                Ok(actual) if actual.eq_ignore_ascii_case(hash) => Outcome::Passed,
+                Ok(_) => Outcome::Failed,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Outcome::Missing,
+                Err(error) => return Err(error),
+            };
+            results.push(Verification {
+                relative,
+                outcome,
+            });
+        }
+        Ok(results)
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_names_are_written_as_is() {
+        assert_eq!(manifest_line("ab12", "src/main.rs"), "ab12  src/main.rs\n");
+    }
+
+    #[test]
+    fn escaped_names_round_trip() {
+        for name in ["back\\slash", "line\nbreak", "carriage\rreturn", "all\\n\n\r\\"] {
+            let line = manifest_line("ab12", name);
+            let escaped = line.strip_prefix("\\ab12  ").unwrap().strip_suffix('\n').unwrap();
+            assert!(!escaped.contains(['\n', '\r']));
+            assert_eq!(unescape(escaped).as_deref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn unknown_escapes_are_refused() {
+        assert_eq!(unescape("a\\tb"), None);
+        assert_eq!(unescape("trailing\\"), None);
+        assert_eq!(unescape("plain").as_deref(), Some("plain"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn manifests_verify_what_they_wrote() {
+        let folder =
+            std::env::temp_dir().join(format!("rust-vfs-manifest-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(folder.join("sub")).unwrap();
+        std::fs::write(folder.join("line\nbreak"), "one").unwrap();
+        std::fs::write(folder.join("back\\slash"), "two").unwrap();
+        std::fs::write(folder.join("sub/plain"), "three").unwrap();
+
+        let (manifest, count) = write_manifest(folder.clone(), HashAlgorithm::Sha256)
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+        std::fs::write(folder.join("sub/plain"), "changed").unwrap();
+        std::fs::remove_file(folder.join("back\\slash")).unwrap();
+
+        let mut results: Vec<(String, Outcome)> = verify_manifest(manifest)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|result| (result.relative, result.outcome))
+            .collect();
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            results,
+            [
+                ("back\\slash".to_string(), Outcome::Missing),
+                ("line\nbreak".to_string(), Outcome::Passed),
+                ("sub/plain".to_string(), Outcome::Failed),
+            ]
+        );
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
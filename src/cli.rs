@@ -0,0 +1,261 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::filter::ListingFilter;
+use crate::links::LinkMode;
+use crate::{
+    archive, create_folder, delete_file, move_into, paste_into, read_directory, read_file,
+    search, Error, FileType,
+};
+
+const COMMANDS: [&str; 7] = ["ls", "cat", "cp", "mv", "rm", "mkdir", "find"];
+
+const USAGE: &str = "\
+Usage: rust-vfs [COMMAND] [ARGS]
+
+Without a command the window is opened.
+
+Commands:
+  ls [-a] [PATH]...        list a folder or archive, -a includes dotfiles
+  cat FILE...              print files, also inside archives
+  cp [-L] SOURCE... FOLDER copy files and folders into FOLDER, -L copies what links point to
+  mv SOURCE... FOLDER      move files and folders into FOLDER
+  rm [-r] PATH...          delete files, -r also deletes folders
+  mkdir [-p] PATH...       create folders, -p also creates parents
+  find [ROOT] PATTERN      find names by fuzzy match or glob like '**/*.rs'";
+
+pub fn is_command(args: &[String]) -> bool {
+    args.first().is_some_and(|first| {
+        COMMANDS.contains(&first.as_str()) || first == "--help" || first == "-h"
+    })
+}
+
+// Runs one command to completion and returns the process exit code
+pub fn run(args: Vec<String>) -> i32 {
+    let runtime = match tokio::runtime::Builder::new_multi_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(error) => {
+            eprintln!("rust-vfs: {}", error);
+            return 1;
+        }
+    };
+    runtime.block_on(run_command(args))
+}
+
+async fn run_command(args: Vec<String>) -> i32 {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => ("--help", &[][..]),
+    };
+    let (flags, operands): (Vec<&String>, Vec<&String>) =
+        rest.iter().partition(|arg| arg.starts_with('-') && arg.len() > 1);
+    let flag = |name: &str| flags.iter().any(|arg| arg.as_str() == name);
+    // Each command has at most one option, anything else is a mistake and not ignored
+    let known = match command {
+        "ls" => "-a",
+        "cp" => "-L",
+        "rm" => "-r",
+        "mkdir" => "-p",
+        _ => "",
+    };
+    let unknown = flags.iter().find(|arg| arg.as_str() != known);
+    if let Some(unknown) = unknown.filter(|_| COMMANDS.contains(&command)) {
+        eprintln!(
+            "rust-vfs {}: unknown option {}\nUsage: rust-vfs {}",
+            command,
+            unknown,
+            usage_of(command)
+        );
+        return 2;
+    }
+    let paths: Vec<PathBuf> = operands.iter().map(PathBuf::from).collect();
+
+    let result = match command {
+        "ls" => ls(paths, flag("-a")).await,
+        "cat" => cat(paths).await,
+        "cp" => transfer(paths, false, flag("-L")).await,
+        "mv" => transfer(paths, true, false).await,
+        "rm" => rm(paths, flag("-r")).await,
+        "mkdir" => mkdir(paths, flag("-p")).await,
+        "find" => find(operands).await,
+        _ => {
+            let _ = writeln!(io::stdout(), "{}", USAGE);
+            return 0;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            eprintln!("rust-vfs {}: {}", command, message);
+            1
+        }
+    }
+}
+
+fn failed(path: &Path, error: Error) -> String {
+    format!("{}: {}", path.display(), error)
+}
+
+// The command's line of USAGE, without its description
+fn usage_of(command: &str) -> &'static str {
+    match command {
+        "ls" => "ls [-a] [PATH]...",
+        "cat" => "cat FILE...",
+        "cp" => "cp [-L] SOURCE... FOLDER",
+        "mv" => "mv SOURCE... FOLDER",
+        "rm" => "rm [-r] PATH...",
+        "mkdir" => "mkdir [-p] PATH...",
+        _ => "find [ROOT] PATTERN",
+    }
+}
+
+fn usage_error(command: &str) -> String {
+    format!("missing operand\nUsage: rust-vfs {}", usage_of(command))
+}
+
+async fn ls(mut paths: Vec<PathBuf>, all: bool) -> Result<(), String> {
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+    let filter = ListingFilter {
+        show_hidden: all,
+        ..ListingFilter::default()
+    };
+    let many = paths.len() > 1;
+    let mut stdout = io::stdout().lock();
+    for path in paths {
+        let (_, entries) = read_directory(path.clone())
+            .await
+            .map_err(|error| failed(&path, error))?;
+        if many {
+            let _ = writeln!(stdout, "{}:", path.display());
+        }
+        let mut names: Vec<String> = entries
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .map(|entry| match entry {
+                FileType::Dir(name) => format!("{}/", name),
+                FileType::File(name) => name.clone(),
+                FileType::Link { name, target, .. } => {
+                    format!("{} -> {}", name, target.display())
+                }
+            })
+            .collect();
+        names.sort();
+        for name in names {
+            let _ = writeln!(stdout, "{}", name);
+        }
+    }
+    Ok(())
+}
+
+async fn cat(paths: Vec<PathBuf>) -> Result<(), String> {
+    if paths.is_empty() {
+        return Err(usage_error("cat"));
+    }
+    let mut stdout = io::stdout().lock();
+    for path in paths {
+        let (_, content) = read_file(path.clone())
+            .await
+            .map_err(|error| failed(&path, error))?;
+        let _ = stdout.write_all(content.as_bytes());
+    }
+    Ok(())
+}
+
+// The last path is the destination folder, like Paste into the folder that is open
+async fn transfer(mut paths: Vec<PathBuf>, moving: bool, follow: bool) -> Result<(), String> {
+    if paths.len() < 2 {
+        return Err(usage_error(if moving { "mv" } else { "cp" }));
+    }
+    let destination = paths.pop().unwrap_or_default();
+    if !destination.is_dir() {
+        return Err(failed(&destination, Error::IOErr(io::ErrorKind::NotADirectory)));
+    }
+    // Nothing can be removed from inside an archive, so moving out of one is refused before
+    // anything is extracted
+    if moving {
+        if let Some(source) = paths
+            .iter()
+            .find(|source| archive::split_archive_path(source).is_some())
+        {
+            return Err(failed(source, Error::IOErr(io::ErrorKind::ReadOnlyFilesystem)));
+        }
+    }
+    let links = if follow {
+        LinkMode::Follow
+    } else {
+        LinkMode::Preserve
+    };
+    for source in paths {
+        let moved = if moving {
+            move_into(&source, &destination, false, links).await
+        } else {
+            paste_into(&source, &destination, links).await.map(|_| ())
+        };
+        moved.map_err(|error| failed(&source, error))?;
+    }
+    Ok(())
+}
+
+async fn remove(path: &Path, recursive: bool) -> Result<(), String> {
+    if path.is_dir() {
+        if !recursive {
+            return Err(failed(path, Error::IOErr(io::ErrorKind::IsADirectory)));
+        }
+        return tokio::fs::remove_dir_all(path)
+            .await
+            .map_err(|error| failed(path, Error::IOErr(error.kind())));
+    }
+    delete_file(Some(path.to_path_buf()))
+        .await
+        .map(|_| ())
+        .map_err(|error| failed(path, error))
+}
+
+async fn rm(paths: Vec<PathBuf>, recursive: bool) -> Result<(), String> {
+    if paths.is_empty() {
+        return Err(usage_error("rm"));
+    }
+    for path in paths {
+        remove(&path, recursive).await?;
+    }
+    Ok(())
+}
+
+async fn mkdir(paths: Vec<PathBuf>, parents: bool) -> Result<(), String> {
+    if paths.is_empty() {
+        return Err(usage_error("mkdir"));
+    }
+    for path in paths {
+        if parents {
+            tokio::fs::create_dir_all(&path)
+                .await
+                .map_err(|error| failed(&path, Error::IOErr(error.kind())))?;
+        } else if path.is_dir() {
+            // create_folder would ask for a name through a dialog here
+            return Err(failed(&path, Error::IOErr(io::ErrorKind::AlreadyExists)));
+        } else {
+            create_folder(Some(path.clone()))
+                .await
+                .map_err(|error| failed(&path, error))?;
+        }
+    }
+    Ok(())
+}
+
+async fn find(operands: Vec<&String>) -> Result<(), String> {
+    let (root, pattern) = match operands.as_slice() {
+        [pattern] => (PathBuf::from("."), pattern.as_str()),
+        [root, pattern] => (PathBuf::from(root), pattern.as_str()),
+        _ => return Err(usage_error("find")),
+    };
+    let (_, index) = search::build_index(root.clone())
+        .await
+        .map_err(|error| failed(&root, error))?;
+    let mut stdout = io::stdout().lock();
+    for entry in search::search(&index, pattern, usize::MAX) {
+        let _ = writeln!(stdout, "{}", entry.path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::links::{self, LinkMode};
+use crate::listing::Side;
+use crate::{copy_file, copy_foldder, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    OnlyLeft,
+    OnlyRight,
+    Changed,
+}
+
+// One line of the difference tree. A folder found on both sides is only listed when
+// something inside it differs, its children follow it one level deeper
+#[derive(Debug, Clone)]
+pub struct DiffEntry {
+    pub relative: String,
+    pub name: String,
+    pub depth: usize,
+    pub is_dir: bool,
+    pub kind: DiffKind,
+}
+
+impl DiffEntry {
+    pub fn exists_on(&self, side: Side) -> bool {
+        match side {
+            Side::Left => self.kind != DiffKind::OnlyRight,
+            Side::Right => self.kind != DiffKind::OnlyLeft,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Comparison {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    pub contents: bool,
+    pub running: bool,
+    pub entries: Vec<DiffEntry>,
+}
+
+impl Comparison {
+    pub fn root(&self, side: Side) -> &Path {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+}
+
+struct Meta {
+    is_dir: bool,
+    size: u64,
+    modified: Option<u64>,
+}
+
+// Walks both trees at once. Without contents, files differ when their size or mtime does,
+// with contents same sized files are hashed and the mtime is ignored
+pub async fn compare_folders(
+    left: PathBuf,
+    right: PathBuf,
+    contents: bool,
+) -> Result<Vec<DiffEntry>, Error> {
+    tokio::task::spawn_blocking(move || {
+        let mut entries = Vec::new();
+        compare_dir(&left, &right, "", 0, contents, &mut entries)?;
+        Ok(entries)
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+fn compare_dir(
+    left: &Path,
+    right: &Path,
+    relative: &str,
+    depth: usize,
+    contents: bool,
+    entries: &mut Vec<DiffEntry>,
+) -> io::Result<bool> {
+    let left_names = read_names(left)?;
+    let right_names = read_names(right)?;
+    let names: BTreeSet<&String> = left_names.keys().chain(right_names.keys()).collect();
+
+    let mut differs = false;
+    for name in names {
+        let child = if relative.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        let (is_dir, kind) = match (left_names.get(name), right_names.get(name)) {
+            (Some(meta), None) => (meta.is_dir, DiffKind::OnlyLeft),
+            (None, Some(meta)) => (meta.is_dir, DiffKind::OnlyRight),
+            (Some(left_meta), Some(right_meta)) if left_meta.is_dir && right_meta.is_dir => {
+                let at = entries.len();
+                entries.push(DiffEntry {
+                    relative: child.clone(),
+                    name: name.clone(),
+                    depth,
+                    is_dir: true,
+                    kind: DiffKind::Changed,
+                });
+                let inner = compare_dir(
+                    &left.join(name),
+                    &right.join(name),
+                    &child,
+                    depth + 1,
+                    contents,
+                    entries,
+                )?;
+                if !inner {
+                    entries.truncate(at);
+                }
+                differs |= inner;
+                continue;
+            }
+            (Some(left_meta), Some(right_meta)) => {
+                let changed = left_meta.is_dir != right_meta.is_dir
+                    || files_differ(
+                        &left.join(name),
+                        left_meta,
+                        &right.join(name),
+                        right_meta,
+                        contents,
+                    )?;
+                if !changed {
+                    continue;
+                }
+                (left_meta.is_dir, DiffKind::Changed)
+            }
+            (None, None) => continue,
+        };
+        entries.push(DiffEntry {
+            relative: child,
+            name: name.clone(),
+            depth,
+            is_dir,
+            kind,
+        });
+        differs = true;
+    }
+    Ok(differs)
+}
+
+fn read_names(dir: &Path) -> io::Result<BTreeMap<String, Meta>> {
+    let mut names = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        // Whole seconds, copies on file systems with coarser timestamps still match
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs());
+        names.insert(
+            entry.file_name().to_string_lossy().into_owned(),
+            Meta {
+                is_dir: meta.is_dir(),
+                size: meta.len(),
+                modified,
+            },
+        );
+    }
+    Ok(names)
+}
+
+fn files_differ(
+    left: &Path,
+    left_meta: &Meta,
+    right: &Path,
+    right_meta: &Meta,
+    contents: bool,
+) -> io::Result<bool> {
+    if left_meta.size != right_meta.size {
+        return Ok(true);
+    }
+    if contents {
+        return Ok(hash_file(left)? != hash_file(right)?);
+    }
+    Ok(left_meta.modified != right_meta.modified)
+}
+
+pub fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.digest());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+// Copies one entry to the same place under the other root, replacing what is there.
+// Folders are merged, so a changed folder only gains and overwrites files. Links are
+// handled the way Paste handles them
+pub async fn copy_across(
+    from: PathBuf,
+    to: PathBuf,
+    relative: String,
+    links: LinkMode,
+) -> Result<PathBuf, Error> {
+    let source = from.join(&relative);
+    let target = to.join(&relative);
+    let parent = target.parent().unwrap_or(&to).to_path_buf();
+    tokio::fs::create_dir_all(&parent)
+        .await
+        .map_err(|error| Error::IOErr(error.kind()))?;
+    if links == LinkMode::Preserve && links::is_symlink(&source) {
+        if target.is_dir() && !links::is_symlink(&target) {
+            tokio::fs::remove_dir_all(&target).await
+        } else {
+            tokio::fs::remove_file(&target).await
+        }
+        .or_else(|error| match error.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            kind => Err(Error::IOErr(kind)),
+        })?;
+        links::copy_symlink(&source, &target).await?;
+        Ok(target)
+    } else if source.is_dir() {
+        // A link in the way is replaced, never written through
+        if links::is_symlink(&target) || target.is_file() {
+            tokio::fs::remove_file(&target)
+                .await
+                .map_err(|error| Error::IOErr(error.kind()))?;
+        }
+        copy_foldder(&source, &parent, links).await
+    } else {
+        if links::is_symlink(&target) {
+            tokio::fs::remove_file(&target)
+                .await
+                .map_err(|error| Error::IOErr(error.kind()))?;
+        } else if target.is_dir() {
+            tokio::fs::remove_dir_all(&target)
+                .await
+                .map_err(|error| Error::IOErr(error.kind()))?;
+        }
+        copy_file(&source, &parent).await
+    }
+}
+
+// What a verified copy found. Paths are relative to the copied entry
+#[derive(Debug, Clone)]
+pub struct CopyCheck {
+    pub checked: usize,
+    pub mismatched: Vec<String>,
+}
+
+// Hashes every file of a copied file or folder against the copy. A file missing from the
+// copy counts as a mismatch. A "copy" that is the source itself proves nothing and is refused
+pub async fn verify_copy(source: PathBuf, copy: PathBuf) -> Result<CopyCheck, Error> {
+    tokio::task::spawn_blocking(move || {
+        if links::same_file(&source, &copy) {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+        let mut check = CopyCheck {
+            checked: 0,
+            mismatched: Vec::new(),
+        };
+        verify_entry(&source, &copy, "", &mut check)?;
+        Ok(check)
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+fn verify_entry(
+    source: &Path,
+    copy: &Path,
+    relative: &str,
+    check: &mut CopyCheck,
+) -> io::Result<()> {
+    let meta = std::fs::symlink_metadata(source)?;
+    if meta.is_dir() {
+        for entry in std::fs::read_dir(source)? {
+            let name = entry?.file_name();
+            let child = if relative.is_empty() {
+                name.to_string_lossy().into_owned()
+            } else {
+                format!("{}/{}", relative, name.to_string_lossy())
+            };
+            verify_entry(&source.join(&name), &copy.join(&name), &child, check)?;
+        }
+    } else if meta.is_file() {
+        check.checked += 1;
+        let same = match hash_file(copy) {
+            Ok(hash) => hash == hash_file(source)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => false,
+            Err(error) => return Err(error),
+        };
+        if !same {
+            let shown = if relative.is_empty() {
+                source
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            } else {
+                relative.to_string()
+            };
+            check.mismatched.push(shown);
+        }
+    }
+    Ok(())
+}
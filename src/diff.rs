@@ -0,0 +1,538 @@
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::listing::Side;
+use crate::{archive, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+// Myers keeps a row per edit, each as long as the edits so far, so the memory grows with the
+// square of the edit count. Past this many edits the texts are too different to show
+const MAX_EDITS: isize = 1000;
+
+// Texts longer than this together aren't compared at all
+const MAX_LINES: usize = 200_000;
+
+// Unchanged lines shown around each hunk, longer runs in between are folded into one row
+const CONTEXT: usize = 3;
+
+// Shortest edit script from a to b, common ends are matched up front so the usual
+// case of a few changes in a long file stays cheap. None when it needs more than MAX_EDITS
+pub fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Edit>> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let middle_a = &a[prefix..a.len() - suffix];
+    let middle_b = &b[prefix..b.len() - suffix];
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+    edits.extend(
+        myers(middle_a, middle_b)?
+            .into_iter()
+            .map(|edit| match edit {
+                Edit::Equal(i, j) => Edit::Equal(i + prefix, j + prefix),
+                Edit::Delete(i) => Edit::Delete(i + prefix),
+                Edit::Insert(j) => Edit::Insert(j + prefix),
+            }),
+    );
+    edits.extend((0..suffix).map(|i| Edit::Equal(a.len() - suffix + i, b.len() - suffix + i)));
+    Some(edits)
+}
+
+// Myers' O(ND) algorithm. trace[d] holds the furthest x reached on every diagonal
+// k in -d..=d after d edits, which is all the backtrack needs
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'search: for d in 0..=(n + m) {
+        if d > MAX_EDITS {
+            return None;
+        }
+        let mut current = vec![0; (2 * d + 1) as usize];
+        for k in (-d..=d).step_by(2) {
+            let mut x = match trace.last() {
+                None => 0,
+                Some(previous) => {
+                    let at = |k: isize| previous[(k + d - 1) as usize];
+                    if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                        at(k + 1)
+                    } else {
+                        at(k - 1) + 1
+                    }
+                }
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            current[(k + d) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(current);
+                break 'search;
+            }
+        }
+        trace.push(current);
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[(d - 1) as usize];
+        let at = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+        if x == previous_x {
+            y -= 1;
+            edits.push(Edit::Insert(y as usize));
+        } else {
+            x -= 1;
+            edits.push(Edit::Delete(x as usize));
+        }
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        edits.push(Edit::Equal(x as usize, y as usize));
+    }
+    edits.reverse();
+    Some(edits)
+}
+
+// Where a side's text came from, the open buffer is compared against the file it was loaded from
+#[derive(Debug, Clone)]
+pub enum Source {
+    File(PathBuf),
+    Buffer(PathBuf),
+    // Text kept for the file elsewhere, by its history or a swap file, with where it came from
+    Version(PathBuf, String),
+}
+
+impl Source {
+    // Files inside an archive and kept versions can be compared but not written back
+    fn writable(&self) -> bool {
+        match self {
+            Source::File(path) => archive::split_archive_path(path).is_none(),
+            Source::Buffer(_) => true,
+            Source::Version(..) => false,
+        }
+    }
+}
+
+// Lines are kept without their endings, a file written back gets the ending of its first line
+#[derive(Debug, Clone)]
+pub struct DiffSide {
+    pub source: Source,
+    pub lines: Vec<String>,
+    pub line_ending: &'static str,
+    pub trailing_newline: bool,
+    pub dirty: bool,
+    pub writable: bool,
+}
+
+impl DiffSide {
+    pub fn new(source: Source, text: &str) -> Self {
+        let crlf = text.find('\n').is_some_and(|end| text[..end].ends_with('\r'));
+        Self {
+            lines: text.lines().map(str::to_string).collect(),
+            line_ending: if crlf { "\r\n" } else { "\n" },
+            trailing_newline: text.ends_with('\n'),
+            dirty: false,
+            writable: source.writable(),
+            source,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = self.lines.join(self.line_ending);
+        if self.trailing_newline && !self.lines.is_empty() {
+            text.push_str(self.line_ending);
+        }
+        text
+    }
+}
+
+// A run of words that is either shared by both lines or only on this side
+pub type Segment = (String, bool);
+
+// A row with neither side stands for `folded` unchanged lines that aren't shown
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub left: Option<(usize, Vec<Segment>)>,
+    pub right: Option<(usize, Vec<Segment>)>,
+    pub hunk: Option<usize>,
+    pub folded: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    pub left: Range<usize>,
+    pub right: Range<usize>,
+    pub first_row: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextDiff {
+    pub left: DiffSide,
+    pub right: DiffSide,
+    pub rows: Vec<Row>,
+    pub hunks: Vec<Hunk>,
+    pub current: usize,
+    // Past the size or edit limits, nothing is shown side by side
+    pub too_different: bool,
+}
+
+impl TextDiff {
+    pub fn new(left: DiffSide, right: DiffSide) -> Self {
+        let mut diff = Self {
+            left,
+            right,
+            rows: Vec::new(),
+            hunks: Vec::new(),
+            current: 0,
+            too_different: false,
+        };
+        diff.rebuild();
+        diff
+    }
+
+    // Big files take a while, so the diff is worked out off the UI thread
+    pub async fn compute(left: DiffSide, right: DiffSide) -> Result<Self, Error> {
+        tokio::task::spawn_blocking(move || Self::new(left, right))
+            .await
+            .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))
+    }
+
+    pub fn side(&self, side: Side) -> &DiffSide {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    pub fn side_mut(&mut self, side: Side) -> &mut DiffSide {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    // Lines of a hunk are paired up in order so changed lines get a word diff, whatever
+    // is left over on the longer side is shown against an empty line
+    fn rebuild(&mut self) {
+        self.rows.clear();
+        self.hunks.clear();
+        let edits = if self.left.lines.len() + self.right.lines.len() > MAX_LINES {
+            None
+        } else {
+            diff(&self.left.lines, &self.right.lines)
+        };
+        self.too_different = edits.is_none();
+        let Some(edits) = edits else {
+            self.current = 0;
+            return;
+        };
+        let (mut next_left, mut next_right) = (0, 0);
+        let mut deleted = Vec::new();
+        let mut inserted = Vec::new();
+        let mut unchanged = Vec::new();
+        for edit in edits.into_iter().map(Some).chain([None]) {
+            match edit {
+                Some(Edit::Delete(i)) => deleted.push(i),
+                Some(Edit::Insert(j)) => inserted.push(j),
+                Some(Edit::Equal(..)) | None => {
+                    if !deleted.is_empty() || !inserted.is_empty() {
+                        self.push_unchanged(&unchanged, true);
+                        unchanged.clear();
+                        self.push_hunk(next_left, next_right, &deleted, &inserted);
+                        next_left += deleted.len();
+                        next_right += inserted.len();
+                        deleted.clear();
+                        inserted.clear();
+                    }
+                    match edit {
+                        Some(Edit::Equal(i, j)) => {
+                            unchanged.push((i, j));
+                            next_left = i + 1;
+                            next_right = j + 1;
+                        }
+                        _ => self.push_unchanged(&unchanged, false),
+                    }
+                }
+            }
+        }
+        self.current = self.current.min(self.hunks.len().saturating_sub(1));
+    }
+
+    // Only the context next to a hunk is shown, a single line is never worth a fold
+    fn push_unchanged(&mut self, lines: &[(usize, usize)], before_hunk: bool) {
+        let leading = if self.hunks.is_empty() { 0 } else { CONTEXT };
+        let trailing = if before_hunk { CONTEXT } else { 0 };
+        let row = |&(i, j): &(usize, usize)| Row {
+            left: Some((i, vec![(self.left.lines[i].clone(), false)])),
+            right: Some((j, vec![(self.right.lines[j].clone(), false)])),
+            hunk: None,
+            folded: 0,
+        };
+        if lines.len() <= leading + trailing + 1 {
+            let rows: Vec<Row> = lines.iter().map(row).collect();
+            self.rows.extend(rows);
+            return;
+        }
+        let shown = lines[..leading]
+            .iter()
+            .map(row)
+            .chain([Row {
+                left: None,
+                right: None,
+                hunk: None,
+                folded: lines.len() - leading - trailing,
+            }])
+            .chain(lines[lines.len() - trailing..].iter().map(row))
+            .collect::<Vec<Row>>();
+        self.rows.extend(shown);
+    }
+
+    fn push_hunk(
+        &mut self,
+        left_start: usize,
+        right_start: usize,
+        deleted: &[usize],
+        inserted: &[usize],
+    ) {
+        let index = self.hunks.len();
+        self.hunks.push(Hunk {
+            left: left_start..left_start + deleted.len(),
+            right: right_start..right_start + inserted.len(),
+            first_row: self.rows.len(),
+        });
+        for row in 0..deleted.len().max(inserted.len()) {
+            let left = deleted.get(row).map(|&i| &self.left.lines[i]);
+            let right = inserted.get(row).map(|&j| &self.right.lines[j]);
+            let (left_segments, right_segments) = match (left, right) {
+                (Some(left), Some(right)) => word_diff(left, right),
+                (left, right) => (
+                    left.map(|line| vec![(line.clone(), true)])
+                        .unwrap_or_default(),
+                    right
+                        .map(|line| vec![(line.clone(), true)])
+                        .unwrap_or_default(),
+                ),
+            };
+            self.rows.push(Row {
+                left: deleted.get(row).map(|&i| (i, left_segments)),
+                right: inserted.get(row).map(|&j| (j, right_segments)),
+                hunk: Some(index),
+                folded: 0,
+            });
+        }
+    }
+
+    // Replaces the current hunk on the other side with this side's lines
+    pub fn copy_hunk(&mut self, from: Side) {
+        let Some(hunk) = self.hunks.get(self.current) else {
+            return;
+        };
+        let (source, target) = match from {
+            Side::Left => (hunk.left.clone(), hunk.right.clone()),
+            Side::Right => (hunk.right.clone(), hunk.left.clone()),
+        };
+        let lines: Vec<String> = self.side(from).lines[source].to_vec();
+        let to = self.side_mut(from.other());
+        to.lines.splice(target, lines);
+        to.dirty = true;
+        self.rebuild();
+    }
+}
+
+// Words, runs of spaces and single punctuation marks, so a renamed variable doesn't
+// mark the whole line as changed
+fn tokens(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut chars = line.char_indices().peekable();
+    while let Some((_, ch)) = chars.next() {
+        let same_kind = |next: char| {
+            (ch.is_alphanumeric() || ch == '_') && (next.is_alphanumeric() || next == '_')
+                || ch.is_whitespace() && next.is_whitespace()
+        };
+        match chars.peek() {
+            Some(&(_, next)) if same_kind(next) => continue,
+            Some(&(next_index, _)) => {
+                tokens.push(&line[start..next_index]);
+                start = next_index;
+            }
+            None => tokens.push(&line[start..]),
+        }
+    }
+    tokens
+}
+
+fn word_diff(left: &str, right: &str) -> (Vec<Segment>, Vec<Segment>) {
+    let left_tokens = tokens(left);
+    let right_tokens = tokens(right);
+    let mut left_segments: Vec<Segment> = Vec::new();
+    let mut right_segments: Vec<Segment> = Vec::new();
+    let push = |segments: &mut Vec<Segment>, token: &str, changed: bool| match segments.last_mut() {
+        Some((text, last)) if *last == changed => text.push_str(token),
+        _ => segments.push((token.to_string(), changed)),
+    };
+    // Lines with nothing in common are marked changed as a whole
+    let Some(edits) = diff(&left_tokens, &right_tokens) else {
+        return (vec![(left.to_string(), true)], vec![(right.to_string(), true)]);
+    };
+    for edit in edits {
+        match edit {
+            Edit::Equal(i, j) => {
+                push(&mut left_segments, left_tokens[i], false);
+                push(&mut right_segments, right_tokens[j], false);
+            }
+            Edit::Delete(i) => push(&mut left_segments, left_tokens[i], true),
+            Edit::Insert(j) => push(&mut right_segments, right_tokens[j], true),
+        }
+    }
+    (left_segments, right_segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&str> {
+        text.split(' ').filter(|line| !line.is_empty()).collect()
+    }
+
+    // Walks the script, every line of a is kept or deleted and every line of b is kept or
+    // inserted, in order, and the result is b
+    fn apply(a: &[&str], b: &[&str], edits: &[Edit]) -> Vec<String> {
+        let (mut next_a, mut next_b) = (0, 0);
+        let mut out = Vec::new();
+        for edit in edits {
+            match *edit {
+                Edit::Equal(i, j) => {
+                    assert_eq!((i, j), (next_a, next_b));
+                    assert_eq!(a[i], b[j]);
+                    out.push(a[i].to_string());
+                    next_a += 1;
+                    next_b += 1;
+                }
+                Edit::Delete(i) => {
+                    assert_eq!(i, next_a);
+                    next_a += 1;
+                }
+                Edit::Insert(j) => {
+                    assert_eq!(j, next_b);
+                    out.push(b[j].to_string());
+                    next_b += 1;
+                }
+            }
+        }
+        assert_eq!((next_a, next_b), (a.len(), b.len()));
+        out
+    }
+
+    fn side(text: &str) -> DiffSide {
+        DiffSide::new(Source::Buffer(PathBuf::new()), text)
+    }
+
+    fn numbered(numbers: impl Iterator<Item = usize>) -> String {
+        numbers.map(|n| format!("{}\n", n)).collect()
+    }
+
+    fn changes(edits: &[Edit]) -> usize {
+        edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Equal(..)))
+            .count()
+    }
+
+    #[test]
+    fn diffs_round_trip() {
+        let cases = [
+            ("", ""),
+            ("a b c", ""),
+            ("", "a b c"),
+            ("a b c", "a b c"),
+            ("a b c a b b a", "c b a b a c"),
+            ("x a b c y", "x b c d y"),
+            ("a a a b", "b a a a"),
+            ("one two three four five", "zero one three four six five"),
+        ];
+        for (a, b) in cases {
+            let (a, b) = (lines(a), lines(b));
+            let edits = diff(&a, &b).unwrap();
+            assert_eq!(apply(&a, &b, &edits), b, "{:?} -> {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn diffs_are_shortest() {
+        let edits = diff(&lines("a b c a b b a"), &lines("c b a b a c")).unwrap();
+        assert_eq!(changes(&edits), 5);
+        let edits = diff(&lines("x a b c y"), &lines("x b c d y")).unwrap();
+        assert_eq!(changes(&edits), 2);
+    }
+
+    #[test]
+    fn gives_up_past_the_edit_limit() {
+        let a: Vec<usize> = (0..600).collect();
+        let b: Vec<usize> = (1000..1600).collect();
+        assert!(diff(&a, &b).is_none());
+        let diff = TextDiff::new(side(&numbered(0..600)), side(&numbered(1000..1600)));
+        assert!(diff.too_different);
+        assert!(diff.rows.is_empty());
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let crlf = side("a\r\nb\r\n");
+        assert_eq!(crlf.lines, ["a", "b"]);
+        assert_eq!(crlf.text(), "a\r\nb\r\n");
+        assert_eq!(side("a\nb").text(), "a\nb");
+    }
+
+    #[test]
+    fn copied_hunks_keep_the_target_line_ending() {
+        let mut diff = TextDiff::new(side("a\nchanged\nc\n"), side("a\r\nb\r\nc\r\n"));
+        assert_eq!(diff.hunks.len(), 1);
+        diff.copy_hunk(Side::Left);
+        assert!(diff.hunks.is_empty());
+        assert_eq!(diff.right.text(), "a\r\nchanged\r\nc\r\n");
+    }
+
+    #[test]
+    fn folds_unchanged_runs() {
+        let old = numbered(0..100);
+        let new = old.replace("\n50\n", "\nfifty\n");
+        let diff = TextDiff::new(side(&old), side(&new));
+        let folded: Vec<usize> = diff.rows.iter().map(|row| row.folded).collect();
+        let mut expected = vec![47];
+        expected.extend([0; 7]);
+        expected.push(46);
+        assert_eq!(folded, expected);
+        let hunk = &diff.hunks[0];
+        assert_eq!(diff.rows[hunk.first_row].hunk, Some(0));
+        assert_eq!(diff.rows[hunk.first_row].left.as_ref().map(|line| line.0), Some(50));
+    }
+}
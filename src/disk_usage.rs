@@ -0,0 +1,123 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageSort {
+    Size,
+    Name,
+}
+
+impl UsageSort {
+    pub const ALL: [UsageSort; 2] = [UsageSort::Size, UsageSort::Name];
+}
+
+impl fmt::Display for UsageSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsageSort::Size => write!(f, "Largest first"),
+            UsageSort::Name => write!(f, "By name"),
+        }
+    }
+}
+
+// A file, or a folder with the total size of everything under it
+#[derive(Debug, Clone)]
+pub struct UsageNode {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub children: Vec<UsageNode>,
+}
+
+impl UsageNode {
+    pub fn sorted(&self, sort: UsageSort) -> Vec<&UsageNode> {
+        let mut children: Vec<&UsageNode> = self.children.iter().collect();
+        match sort {
+            UsageSort::Size => {
+                children.sort_by(|a, b| b.size.cmp(&a.size).then(a.name.cmp(&b.name)))
+            }
+            UsageSort::Name => children.sort_by(|a, b| a.name.cmp(&b.name)),
+        }
+        children
+    }
+}
+
+#[derive(Debug)]
+pub struct DiskUsage {
+    pub root: PathBuf,
+    pub running: bool,
+    pub tree: Option<UsageNode>,
+    // Names of the folders drilled into, from the root down
+    pub open: Vec<String>,
+    pub sort: UsageSort,
+}
+
+impl DiskUsage {
+    pub fn current(&self) -> Option<&UsageNode> {
+        let mut node = self.tree.as_ref()?;
+        for name in &self.open {
+            node = node.children.iter().find(|child| child.name == *name)?;
+        }
+        Some(node)
+    }
+
+    pub fn current_path(&self) -> PathBuf {
+        self.open
+            .iter()
+            .fold(self.root.clone(), |path, name| path.join(name))
+    }
+}
+
+// Symlinks count as their own small size and aren't followed. Folders that can't be read
+// count as empty rather than failing the whole scan
+pub async fn scan(root: PathBuf) -> Result<UsageNode, Error> {
+    tokio::task::spawn_blocking(move || {
+        let name = root.file_name().map_or_else(
+            || root.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let children = scan_dir(&root)?;
+        Ok(UsageNode {
+            name,
+            is_dir: true,
+            size: children.iter().map(|child| child.size).sum(),
+            children,
+        })
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+fn scan_dir(dir: &Path) -> io::Result<Vec<UsageNode>> {
+    let mut nodes = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let Ok(meta) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if meta.is_dir() {
+            let children = scan_dir(&entry.path()).unwrap_or_default();
+            nodes.push(UsageNode {
+                name,
+                is_dir: true,
+                size: children.iter().map(|child| child.size).sum(),
+                children,
+            });
+        } else {
+            nodes.push(UsageNode {
+                name,
+                is_dir: false,
+                size: meta.len(),
+                children: Vec::new(),
+            });
+        }
+    }
+    Ok(nodes)
+}
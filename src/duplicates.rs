@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::compare::hash_file;
+use crate::Error;
+
+// Files with the same content. `keep` is the copy the group actions leave in place
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    pub paths: Vec<PathBuf>,
+    pub keep: usize,
+}
+
+impl DuplicateGroup {
+    // Space freed by keeping only one copy
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+
+    pub fn others(&self) -> Vec<PathBuf> {
+        self.paths
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != self.keep)
+            .map(|(_, path)| path.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct Duplicates {
+    pub root: PathBuf,
+    pub running: bool,
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl Duplicates {
+    // Drops paths that were trashed or linked, a group with one copy left is done
+    pub fn resolved(&mut self, paths: &[PathBuf]) {
+        for group in &mut self.groups {
+            let keeper = group.paths[group.keep].clone();
+            group.paths.retain(|path| !paths.contains(path));
+            group.keep = group
+                .paths
+                .iter()
+                .position(|path| *path == keeper)
+                .unwrap_or(0);
+        }
+        self.groups.retain(|group| group.paths.len() > 1);
+    }
+}
+
+// Files are grouped by size first, then by a hash of their first 4 KiB and only then by a
+// hash of the whole file, so most files are never read. Symlinks aren't followed and empty
+// files are left out
+pub async fn find_duplicates(root: PathBuf) -> Result<Vec<DuplicateGroup>, Error> {
+    tokio::task::spawn_blocking(move || {
+        let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        collect_files(&root, &mut by_size, &mut HashSet::new())?;
+
+        let mut groups = Vec::new();
+        for (size, paths) in by_size {
+            if paths.len() < 2 {
+                continue;
+            }
+            for candidates in split_by(paths, head_hash)? {
+                for paths in split_by(candidates, hash_file)? {
+                    groups.push(DuplicateGroup {
+                        size,
+                        paths,
+                        keep: 0,
+                    });
+                }
+            }
+        }
+        groups.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then(a.paths.cmp(&b.paths)));
+        Ok(groups)
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+fn collect_files(
+    dir: &Path,
+    by_size: &mut HashMap<u64, Vec<PathBuf>>,
+    seen: &mut HashSet<(u64, u64)>,
+) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            collect_files(&entry.path(), by_size, seen)?;
+            continue;
+        }
+        if !meta.is_file() || meta.len() == 0 {
+            continue;
+        }
+        // Hard links to one file already share their space
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if !seen.insert((meta.dev(), meta.ino())) {
+                continue;
+            }
+        }
+        by_size.entry(meta.len()).or_default().push(entry.path());
+    }
+    Ok(())
+}
+
+// Groups of two or more paths that hash the same, sorted so the first one is kept by default
+fn split_by(
+    paths: Vec<PathBuf>,
+    hash: fn(&Path) -> io::Result<u64>,
+) -> io::Result<Vec<Vec<PathBuf>>> {
+    let mut groups: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+    for path in paths {
+        groups.entry(hash(&path)?).or_default().push(path);
+    }
+    Ok(groups
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .map(|mut paths| {
+            paths.sort();
+            paths
+        })
+        .collect())
+}
+
+fn head_hash(path: &Path) -> io::Result<u64> {
+    let mut head = Vec::with_capacity(4096);
+    File::open(path)?.take(4096).read_to_end(&mut head)?;
+    Ok(xxh3_64(&head))
+}
+
+// The copies that still have the size and content the search found, checked right before
+// they are removed. If the kept file itself changed, none of them are
+fn unchanged(keep: &Path, size: u64, others: Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
+    let same_size = |path: &Path| {
+        std::fs::symlink_metadata(path).is_ok_and(|meta| meta.is_file() && meta.len() == size)
+    };
+    if !same_size(keep) {
+        return Ok(Vec::new());
+    }
+    let hash = hash_file(keep)?;
+    Ok(others
+        .into_iter()
+        .filter(|other| same_size(other) && hash_file(other).is_ok_and(|other| other == hash))
+        .collect())
+}
+
+// Moves the files to the system trash and returns the ones that went and how many were
+// left alone because they changed since the search
+pub async fn trash(
+    keep: PathBuf,
+    size: u64,
+    others: Vec<PathBuf>,
+) -> Result<(Vec<PathBuf>, usize), Error> {
+    tokio::task::spawn_blocking(move || {
+        let count = others.len();
+        let paths = unchanged(&keep, size, others).map_err(|error| Error::IOErr(error.kind()))?;
+        if !paths.is_empty() {
+            trash::delete_all(&paths).map_err(|_| Error::IOErr(io::ErrorKind::Other))?;
+        }
+        let skipped = count - paths.len();
+        Ok((paths, skipped))
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+}
+
+// Replaces every other copy with a hard link to the kept one. The link is made next to
+// the copy first and renamed over it, so a failure never loses the copy
+pub async fn hard_link(
+    keep: PathBuf,
+    size: u64,
+    others: Vec<PathBuf>,
+) -> Result<(Vec<PathBuf>, usize), Error> {
+    tokio::task::spawn_blocking(move || {
+        let count = others.len();
+        let others = unchanged(&keep, size, others)?;
+        for other in &others {
+            let folder = other.parent().unwrap_or(Path::new("."));
+            let temp = folder.join(format!(".link-{}", uuid::Uuid::new_v4()));
+            std::fs::hard_link(&keep, &temp)?;
+            if let Err(error) = std::fs::rename(&temp, other) {
+                let _ = std::fs::remove_file(&temp);
+                return Err(error);
+            }
+        }
+        let skipped = count - others.len();
+        Ok((others, skipped))
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+use crate::FileType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KindFilter {
+    All,
+    Files,
+    Folders,
+}
+
+impl KindFilter {
+    pub const ALL: [KindFilter; 3] = [KindFilter::All, KindFilter::Files, KindFilter::Folders];
+}
+
+impl fmt::Display for KindFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KindFilter::All => write!(f, "All"),
+            KindFilter::Files => write!(f, "Files"),
+            KindFilter::Folders => write!(f, "Folders"),
+        }
+    }
+}
+
+// Kept on FileSystem for the whole session so every folder is listed the same way
+#[derive(Debug, Clone)]
+pub struct ListingFilter {
+    pub show_hidden: bool,
+    pub kind: KindFilter,
+    pub extensions: String,
+    pub query: String,
+}
+
+impl Default for ListingFilter {
+    fn default() -> Self {
+        Self {
+            show_hidden: false,
+            kind: KindFilter::All,
+            extensions: String::new(),
+            query: String::new(),
+        }
+    }
+}
+
+impl ListingFilter {
+    pub fn matches(&self, entry: &FileType) -> bool {
+        let (name, is_dir) = (entry.name(), entry.is_dir());
+        if !self.show_hidden && name.starts_with('.') {
+            return false;
+        }
+        match self.kind {
+            KindFilter::Files if is_dir => return false,
+            KindFilter::Folders if !is_dir => return false,
+            _ => {}
+        }
+        // Folders stay visible under an extension filter so you can still navigate
+        if !is_dir && !self.matches_extension(name) {
+            return false;
+        }
+        let query = self.query.trim().to_lowercase();
+        query.is_empty() || name.to_lowercase().contains(&query)
+    }
+
+    // "rs, toml" or ".rs .toml", empty means every extension
+    fn matches_extension(&self, name: &str) -> bool {
+        let wanted: Vec<String> = self
+            .extensions
+            .split([',', ' '])
+            .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        if wanted.is_empty() {
+            return true;
+        }
+        match name.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => wanted.contains(&ext.to_lowercase()),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{archive, settings, Error};
+
+// How many versions of a file are kept and for how long, whichever runs out first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    pub max_versions: usize,
+    pub max_days: u64,
+}
+
+// A saved copy of a file as it was before one of its saves
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub stored: PathBuf,
+    pub saved: SystemTime,
+    pub size: u64,
+}
+
+impl Version {
+    pub fn label(&self) -> String {
+        archive::time_label(self.saved)
+    }
+}
+
+// The History panel for one file, newest version first
+#[derive(Debug)]
+pub struct History {
+    pub file: PathBuf,
+    pub loading: bool,
+    pub versions: Vec<Version>,
+}
+
+// config/history/<hash of the path>, one file per version named by its time in
+// milliseconds, and a `path` file naming the file they belong to
+fn store(file: &Path) -> Option<PathBuf> {
+    let key = file.to_string_lossy();
+    settings::config_dir().map(|dir| {
+        dir.join("history")
+            .join(format!("{:016x}", xxh3_64(key.as_bytes())))
+    })
+}
+
+// Keeps what is on disk before a save overwrites it. Nothing is kept for a new file or
+// when the newest version already has the same content
+pub async fn record(file: PathBuf, retention: Retention) -> Result<(), Error> {
+    tokio::task::spawn_blocking(move || {
+        let Some(store) = store(&file) else {
+            return Ok(());
+        };
+        let content = match std::fs::read(&file) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        std::fs::create_dir_all(&store)?;
+        std::fs::write(store.join("path"), file.to_string_lossy().as_bytes())?;
+        let versions = read_versions(&store)?;
+        let unchanged = versions
+            .first()
+            .is_some_and(|newest| std::fs::read(&newest.stored).is_ok_and(|kept| kept == content));
+        if !unchanged {
+            let millis = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            std::fs::write(store.join(millis.to_string()), &content)?;
+        }
+        prune(&store, retention)
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+// Versions past the count or older than the age limit are deleted, the newest one always stays.
+// An age limit too large to reach back from now is no limit
+fn prune(store: &Path, retention: Retention) -> io::Result<()> {
+    let oldest = retention
+        .max_days
+        .checked_mul(24 * 60 * 60)
+        .and_then(|secs| SystemTime::now().checked_sub(Duration::from_secs(secs)));
+    for (index, version) in read_versions(store)?.into_iter().enumerate() {
+        let expired = oldest.is_some_and(|oldest| version.saved < oldest);
+        if index > 0 && (index >= retention.max_versions || expired) {
+            std::fs::remove_file(&version.stored)?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn versions(file: PathBuf) -> Result<Vec<Version>, Error> {
+    tokio::task::spawn_blocking(move || match store(&file) {
+        Some(store) if store.is_dir() => read_versions(&store),
+        _ => Ok(Vec::new()),
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+fn read_versions(store: &Path) -> io::Result<Vec<Version>> {
+    let mut versions = Vec::new();
+    for entry in std::fs::read_dir(store)? {
+        let entry = entry?;
+        let Some(millis) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        versions.push(Version {
+            stored: entry.path(),
+            saved: UNIX_EPOCH + Duration::from_millis(millis),
+            size: entry.metadata()?.len(),
+        });
+    }
+    versions.sort_by_key(|version| std::cmp::Reverse(version.saved));
+    Ok(versions)
+}
+
+pub async fn read_version(stored: PathBuf) -> Result<String, Error> {
+    tokio::fs::read_to_string(&stored)
+        .await
+        .map_err(|error| Error::IOErr(error.kind()))
+}
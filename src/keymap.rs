@@ -0,0 +1,477 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use iced::keyboard::{self, key::Named, Key, Modifiers};
+use serde::Deserialize;
+
+use crate::settings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Save,
+    New,
+    OpenFile,
+    OpenFolder,
+    Copy,
+    Cut,
+    Paste,
+    Delete,
+    Refresh,
+    Back,
+    Search,
+    NextTab,
+    PreviousTab,
+    CloseTab,
+    Shortcuts,
+    CommandPalette,
+    CreateFolder,
+    ExtractHere,
+    ExtractTo,
+    Compress,
+    ToggleHidden,
+    ToggleToolbar,
+    ChangeTheme,
+    DualPane,
+    CopyToOther,
+    MoveToOther,
+    SwapPanes,
+    CompareFolders,
+    CompareFiles,
+    CompareWithSaved,
+    FileHistory,
+    NextHunk,
+    PreviousHunk,
+    SyncFolders,
+    BulkRename,
+    FindDuplicates,
+    DiskUsage,
+    Checksums,
+    WriteManifest,
+    VerifyManifest,
+    TakeSnapshot,
+    Snapshots,
+    ToggleVerifyCopies,
+    ToggleFollowLinks,
+    ToggleAutoSave,
+    PasteSymlink,
+    PasteHardLink,
+}
+
+impl Action {
+    pub const ALL: [Action; 47] = [
+        Action::Save,
+        Action::New,
+        Action::OpenFile,
+        Action::OpenFolder,
+        Action::Copy,
+        Action::Cut,
+        Action::Paste,
+        Action::Delete,
+        Action::Refresh,
+        Action::Back,
+        Action::Search,
+        Action::NextTab,
+        Action::PreviousTab,
+        Action::CloseTab,
+        Action::Shortcuts,
+        Action::CommandPalette,
+        Action::CreateFolder,
+        Action::ExtractHere,
+        Action::ExtractTo,
+        Action::Compress,
+        Action::ToggleHidden,
+        Action::ToggleToolbar,
+        Action::ChangeTheme,
+        Action::DualPane,
+        Action::CopyToOther,
+        Action::MoveToOther,
+        Action::SwapPanes,
+        Action::CompareFolders,
+        Action::CompareFiles,
+        Action::CompareWithSaved,
+        Action::FileHistory,
+        Action::NextHunk,
+        Action::PreviousHunk,
+        Action::SyncFolders,
+        Action::BulkRename,
+        Action::FindDuplicates,
+        Action::DiskUsage,
+        Action::Checksums,
+        Action::WriteManifest,
+        Action::VerifyManifest,
+        Action::TakeSnapshot,
+        Action::Snapshots,
+        Action::ToggleVerifyCopies,
+        Action::ToggleFollowLinks,
+        Action::ToggleAutoSave,
+        Action::PasteSymlink,
+        Action::PasteHardLink,
+    ];
+
+    // The key used for the action in keymap.toml
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Save => "save",
+            Action::New => "new",
+            Action::OpenFile => "open_file",
+            Action::OpenFolder => "open_folder",
+            Action::Copy => "copy",
+            Action::Cut => "cut",
+            Action::Paste => "paste",
+            Action::Delete => "delete",
+            Action::Refresh => "refresh",
+            Action::Back => "back",
+            Action::Search => "search",
+            Action::NextTab => "next_tab",
+            Action::PreviousTab => "previous_tab",
+            Action::CloseTab => "close_tab",
+            Action::Shortcuts => "shortcuts",
+            Action::CommandPalette => "command_palette",
+            Action::CreateFolder => "create_folder",
+            Action::ExtractHere => "extract_here",
+            Action::ExtractTo => "extract_to",
+            Action::Compress => "compress",
+            Action::ToggleHidden => "toggle_hidden",
+            Action::ToggleToolbar => "toggle_toolbar",
+            Action::ChangeTheme => "change_theme",
+            Action::DualPane => "dual_pane",
+            Action::CopyToOther => "copy_to_other",
+            Action::MoveToOther => "move_to_other",
+            Action::SwapPanes => "swap_panes",
+            Action::CompareFolders => "compare_folders",
+            Action::CompareFiles => "compare_files",
+            Action::CompareWithSaved => "compare_with_saved",
+            Action::FileHistory => "file_history",
+            Action::NextHunk => "next_hunk",
+            Action::PreviousHunk => "previous_hunk",
+            Action::SyncFolders => "sync_folders",
+            Action::BulkRename => "bulk_rename",
+            Action::FindDuplicates => "find_duplicates",
+            Action::DiskUsage => "disk_usage",
+            Action::Checksums => "checksums",
+            Action::WriteManifest => "write_manifest",
+            Action::VerifyManifest => "verify_manifest",
+            Action::TakeSnapshot => "take_snapshot",
+            Action::Snapshots => "snapshots",
+            Action::ToggleVerifyCopies => "toggle_verify_copies",
+            Action::ToggleFollowLinks => "toggle_follow_links",
+            Action::ToggleAutoSave => "toggle_auto_save",
+            Action::PasteSymlink => "paste_symlink",
+            Action::PasteHardLink => "paste_hard_link",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    // Copy, Cut and Paste leave Ctrl+C/X/V to the text editors, an empty string is unbound
+    fn default_keys(self) -> &'static str {
+        match self {
+            Action::Save => "Ctrl+S",
+            Action::New => "Ctrl+N",
+            Action::OpenFile => "Ctrl+O",
+            Action::OpenFolder => "Ctrl+Shift+O",
+            Action::Copy => "Ctrl+Shift+C",
+            Action::Cut => "Ctrl+Shift+X",
+            Action::Paste => "Ctrl+Shift+V",
+            Action::Delete => "Ctrl+D",
+            Action::Refresh => "Ctrl+R",
+            Action::Back => "Alt+Up",
+            Action::Search => "Ctrl+P",
+            Action::NextTab => "Ctrl+Tab",
+            Action::PreviousTab => "Ctrl+Shift+Tab",
+            Action::CloseTab => "Ctrl+W",
+            Action::Shortcuts => "F1",
+            Action::CommandPalette => "Ctrl+Shift+P",
+            Action::CreateFolder => "Ctrl+Shift+N",
+            Action::DualPane => "F9",
+            Action::CopyToOther => "F5",
+            Action::MoveToOther => "F6",
+            Action::SwapPanes => "Ctrl+U",
+            Action::ExtractHere
+            | Action::ExtractTo
+            | Action::Compress
+            | Action::ToggleHidden
+            | Action::ToggleToolbar
+            | Action::ChangeTheme
+            | Action::CompareFolders
+            | Action::CompareFiles
+            | Action::CompareWithSaved
+            | Action::FileHistory
+            | Action::SyncFolders
+            | Action::FindDuplicates
+            | Action::DiskUsage
+            | Action::Checksums
+            | Action::WriteManifest
+            | Action::VerifyManifest
+            | Action::TakeSnapshot
+            | Action::Snapshots
+            | Action::ToggleVerifyCopies
+            | Action::ToggleFollowLinks
+            | Action::ToggleAutoSave
+            | Action::PasteSymlink
+            | Action::PasteHardLink => "",
+            Action::NextHunk => "F7",
+            Action::PreviousHunk => "Shift+F7",
+            Action::BulkRename => "F2",
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Action::Save => "Save",
+            Action::New => "New File",
+            Action::OpenFile => "Open File",
+            Action::OpenFolder => "Open Folder",
+            Action::Copy => "Copy",
+            Action::Cut => "Cut",
+            Action::Paste => "Paste",
+            Action::Delete => "Delete File",
+            Action::Refresh => "Refresh",
+            Action::Back => "Back",
+            Action::Search => "Search Files",
+            Action::NextTab => "Next Tab",
+            Action::PreviousTab => "Previous Tab",
+            Action::CloseTab => "Close Tab",
+            Action::Shortcuts => "Keyboard Shortcuts",
+            Action::CommandPalette => "Command Palette",
+            Action::CreateFolder => "Create Folder",
+            Action::ExtractHere => "Extract Here",
+            Action::ExtractTo => "Extract To",
+            Action::Compress => "Compress",
+            Action::ToggleHidden => "Show or Hide Dotfiles",
+            Action::ToggleToolbar => "Show or Hide Options",
+            Action::ChangeTheme => "Change Theme",
+            Action::DualPane => "Dual Pane",
+            Action::CopyToOther => "Copy to Other Pane",
+            Action::MoveToOther => "Move to Other Pane",
+            Action::SwapPanes => "Swap Panes",
+            Action::CompareFolders => "Compare Folders",
+            Action::CompareFiles => "Compare Files",
+            Action::CompareWithSaved => "Compare with Saved",
+            Action::FileHistory => "File History",
+            Action::NextHunk => "Next Difference",
+            Action::PreviousHunk => "Previous Difference",
+            Action::SyncFolders => "Sync Folders",
+            Action::BulkRename => "Bulk Rename",
+            Action::FindDuplicates => "Find Duplicates",
+            Action::DiskUsage => "Disk Usage",
+            Action::Checksums => "Calculate Checksums",
+            Action::WriteManifest => "Write Checksum Manifest",
+            Action::VerifyManifest => "Verify Checksum Manifest",
+            Action::TakeSnapshot => "Take Folder Snapshot",
+            Action::Snapshots => "Folder Snapshots",
+            Action::ToggleVerifyCopies => "Verify Copies On or Off",
+            Action::ToggleFollowLinks => "Follow Symlinks When Copying On or Off",
+            Action::ToggleAutoSave => "Auto-Save on Focus Loss On or Off",
+            Action::PasteSymlink => "Paste as Symlink",
+            Action::PasteHardLink => "Paste as Hard Link",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+// One key with its modifiers, Ctrl is Cmd on macOS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    key: String,
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+}
+
+impl Binding {
+    // "Ctrl+Shift+C", "Alt+Up" or "F5", case does not matter
+    fn parse(text: &str) -> Option<Binding> {
+        let mut binding = Binding {
+            key: String::new(),
+            ctrl: false,
+            shift: false,
+            alt: false,
+        };
+        let parts: Vec<String> = text
+            .split('+')
+            .map(|part| part.trim().to_lowercase())
+            .collect();
+        let (key, modifiers) = parts.split_last()?;
+        for modifier in modifiers {
+            match modifier.as_str() {
+                "ctrl" | "cmd" | "control" => binding.ctrl = true,
+                "shift" => binding.shift = true,
+                "alt" | "option" => binding.alt = true,
+                _ => return None,
+            }
+        }
+        let known = key.chars().count() == 1 || NAMED.iter().any(|(name, _, _)| name == key);
+        if !known {
+            return None;
+        }
+        binding.key = key.clone();
+        Some(binding)
+    }
+
+    fn matches(&self, key: &str, modifiers: Modifiers) -> bool {
+        self.key == key
+            && self.ctrl == modifiers.command()
+            && self.shift == modifiers.shift()
+            && self.alt == modifiers.alt()
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        match NAMED.iter().find(|(name, _, _)| *name == self.key) {
+            Some((_, label, _)) => write!(f, "{}", label),
+            None => write!(f, "{}", self.key.to_uppercase()),
+        }
+    }
+}
+
+// Config name, display label and the iced key
+const NAMED: [(&str, &str, Named); 27] = [
+    ("tab", "Tab", Named::Tab),
+    ("enter", "Enter", Named::Enter),
+    ("space", "Space", Named::Space),
+    ("escape", "Escape", Named::Escape),
+    ("backspace", "Backspace", Named::Backspace),
+    ("delete", "Delete", Named::Delete),
+    ("insert", "Insert", Named::Insert),
+    ("home", "Home", Named::Home),
+    ("end", "End", Named::End),
+    ("pageup", "PageUp", Named::PageUp),
+    ("pagedown", "PageDown", Named::PageDown),
+    ("up", "Up", Named::ArrowUp),
+    ("down", "Down", Named::ArrowDown),
+    ("left", "Left", Named::ArrowLeft),
+    ("right", "Right", Named::ArrowRight),
+    ("f1", "F1", Named::F1),
+    ("f2", "F2", Named::F2),
+    ("f3", "F3", Named::F3),
+    ("f4", "F4", Named::F4),
+    ("f5", "F5", Named::F5),
+    ("f6", "F6", Named::F6),
+    ("f7", "F7", Named::F7),
+    ("f8", "F8", Named::F8),
+    ("f9", "F9", Named::F9),
+    ("f10", "F10", Named::F10),
+    ("f11", "F11", Named::F11),
+    ("f12", "F12", Named::F12),
+];
+
+fn key_name(key: &Key) -> Option<String> {
+    match key.as_ref() {
+        keyboard::Key::Character(character) => Some(character.to_lowercase()),
+        keyboard::Key::Named(named) => NAMED
+            .iter()
+            .find(|(_, _, candidate)| *candidate == named)
+            .map(|(name, _, _)| name.to_string()),
+        keyboard::Key::Unidentified => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Binding, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .into_iter()
+            .filter_map(|action| Binding::parse(action.default_keys()).map(|key| (key, action)))
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    pub fn action(&self, key: &Key, modifiers: Modifiers) -> Option<Action> {
+        let name = key_name(key)?;
+        self.bindings
+            .iter()
+            .find(|(binding, _)| binding.matches(&name, modifiers))
+            .map(|(_, action)| *action)
+    }
+
+    // "Ctrl+Shift+C, F3", empty when the action is unbound
+    pub fn keys(&self, action: Action) -> String {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(binding, _)| binding.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// An action maps to one key or a list of them, an empty list unbinds it
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+pub fn keymap_file() -> Option<PathBuf> {
+    settings::config_dir().map(|dir| dir.join("keymap.toml"))
+}
+
+// Read once at startup. Actions missing from the file keep their default keys and
+// anything that doesn't parse is reported and skipped
+pub fn load() -> Keymap {
+    let mut keymap = Keymap::default();
+    let Some(path) = keymap_file() else {
+        return keymap;
+    };
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return keymap;
+    };
+    let overrides: BTreeMap<String, Keys> = match toml::from_str(&text) {
+        Ok(overrides) => overrides,
+        Err(error) => {
+            eprintln!("rust-vfs: ignoring {}: {}", path.display(), error);
+            return keymap;
+        }
+    };
+    for (name, keys) in overrides {
+        let Some(action) = Action::from_name(&name) else {
+            eprintln!("rust-vfs: {}: unknown action {}", path.display(), name);
+            continue;
+        };
+        keymap.bindings.retain(|(_, bound)| *bound != action);
+        let keys = match keys {
+            Keys::One(key) if key.trim().is_empty() => Vec::new(),
+            Keys::One(key) => vec![key],
+            Keys::Many(keys) => keys,
+        };
+        for key in keys {
+            match Binding::parse(&key) {
+                Some(binding) => {
+                    // A key taken over by another action only triggers the new one
+                    keymap.bindings.retain(|(bound, _)| *bound != binding);
+                    keymap.bindings.push((binding, action));
+                }
+                None => eprintln!(
+                    "rust-vfs: {}: unknown key {:?} for {}",
+                    path.display(),
+                    key,
+                    name
+                ),
+            }
+        }
+    }
+    keymap
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+// How copies treat symlinks. Preserve recreates the link and keeps files that are hard
+// linked together inside a copied folder linked in the copy. Follow copies what the link
+// points to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    Preserve,
+    Follow,
+}
+
+pub fn is_symlink(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+// Whether both paths lead to the same file, through symlinks or as hard links of one file.
+// Copying a file onto itself would truncate it
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    let (Ok(first), Ok(second)) = (std::fs::metadata(a), std::fs::metadata(b)) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        (first.dev(), first.ino()) == (second.dev(), second.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (first, second);
+        std::fs::canonicalize(a).ok() == std::fs::canonicalize(b).ok()
+    }
+}
+
+// A new symlink at `link` pointing where `source`, itself a symlink, points
+pub async fn copy_symlink(source: &Path, link: &Path) -> Result<(), Error> {
+    let target = tokio::fs::read_link(source)
+        .await
+        .map_err(|error| Error::IOErr(error.kind()))?;
+    // Relative targets are relative to the link's folder
+    let points_to_dir = source
+        .parent()
+        .map_or_else(|| target.clone(), |folder| folder.join(&target))
+        .is_dir();
+    create_symlink(&target, link, points_to_dir).await
+}
+
+pub async fn create_symlink(target: &Path, link: &Path, is_dir: bool) -> Result<(), Error> {
+    #[cfg(unix)]
+    let created = {
+        let _ = is_dir;
+        tokio::fs::symlink(target, link).await
+    };
+    #[cfg(windows)]
+    let created = if is_dir {
+        tokio::fs::symlink_dir(target, link).await
+    } else {
+        tokio::fs::symlink_file(target, link).await
+    };
+    created.map_err(|error| Error::IOErr(error.kind()))
+}
+
+// Paste as Symlink, a link in the folder named after and pointing to the copied entry
+pub async fn symlink_into(target: &Path, folder: &Path) -> Result<PathBuf, Error> {
+    let link = folder.join(target.file_name().unwrap_or_default());
+    create_symlink(target, &link, target.is_dir()).await?;
+    Ok(link)
+}
+
+// Paste as Hard Link, only files can have more than one name
+pub async fn hard_link_into(target: &Path, folder: &Path) -> Result<PathBuf, Error> {
+    if target.is_dir() {
+        return Err(Error::IOErr(io::ErrorKind::IsADirectory));
+    }
+    let link = folder.join(target.file_name().unwrap_or_default());
+    tokio::fs::hard_link(target, &link)
+        .await
+        .map_err(|error| Error::IOErr(error.kind()))?;
+    Ok(link)
+}
+
+// Files with more than one name met while copying a folder, by device and inode
+#[derive(Debug, Default)]
+pub struct HardLinks(HashMap<(u64, u64), PathBuf>);
+
+impl HardLinks {
+    // The copy already made of the same file, otherwise remembers `copy` as the first one
+    pub fn first_copy(&mut self, meta: &std::fs::Metadata, copy: &Path) -> Option<PathBuf> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if meta.nlink() > 1 {
+                let key = (meta.dev(), meta.ino());
+                if let Some(first) = self.0.get(&key) {
+                    return Some(first.clone());
+                }
+                self.0.insert(key, copy.to_path_buf());
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (meta, copy);
+        None
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+
+use iced::widget::text_editor;
+
+use crate::filter::ListingFilter;
+use crate::{archive, FileType};
+
+// The two panes of the commander page, the single listing on the folder page is Left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    pub fn other(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+}
+
+// A folder as it was last read, shown one entry per line in a read-only text editor
+#[derive(Debug)]
+pub struct Listing {
+    pub root: Option<PathBuf>,
+    // The archive root is inside, worked out once when a folder is shown
+    pub archive: Option<PathBuf>,
+    pub content: text_editor::Content,
+    entries: Vec<FileType>,
+    visible: Vec<FileType>,
+    selected: Option<(usize, usize)>,
+}
+
+impl Default for Listing {
+    fn default() -> Self {
+        Self {
+            root: None,
+            archive: None,
+            content: text_editor::Content::new(),
+            entries: Vec::new(),
+            visible: Vec::new(),
+            selected: None,
+        }
+    }
+}
+
+impl Listing {
+    pub fn show(&mut self, root: PathBuf, entries: Vec<FileType>, filter: &ListingFilter) {
+        self.archive = archive::split_archive_path(&root).map(|(archive_path, _)| archive_path);
+        self.root = Some(root);
+        self.entries = entries;
+        self.render(filter);
+    }
+
+    // Rebuilds the text from the last folder read, applying the session filters
+    pub fn render(&mut self, filter: &ListingFilter) {
+        let mut text = String::new();
+        self.visible = self
+            .entries
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .cloned()
+            .collect();
+        self.selected = None;
+        for filename in &self.visible {
+            match filename {
+                FileType::Dir(var) => {
+                    text.push_str(&format!("📁{}\n", var));
+                }
+                FileType::File(var) if archive::is_archive(Path::new(var)) => {
+                    text.push_str(&format!("📦{}\n", var));
+                }
+                FileType::File(var) => {
+                    text.push_str(&format!("📝{}\n", var));
+                }
+                FileType::Link { name, target, .. } => {
+                    text.push_str(&format!("🔗{} → {}\n", name, target.display()));
+                }
+            }
+        }
+        self.content = text_editor::Content::with_text(&text);
+    }
+
+    // Moves the cursor and selection, a double click returns the entry to open
+    pub fn perform(&mut self, action: text_editor::Action) -> Option<(PathBuf, bool)> {
+        match action {
+            action if action.is_edit() => None,
+            // A double click lands here after the click has moved the cursor to the line
+            text_editor::Action::SelectWord => self.selected_entry(),
+            action => {
+                // Clicks and plain moves start a new selection, drags and shift moves extend it
+                let starts = matches!(
+                    action,
+                    text_editor::Action::Click(_) | text_editor::Action::Move(_)
+                );
+                let extends = matches!(
+                    action,
+                    text_editor::Action::Drag(_) | text_editor::Action::Select(_)
+                );
+                self.content.perform(action);
+                let line = self.content.cursor_position().0;
+                if starts {
+                    self.selected = Some((line, line));
+                } else if extends {
+                    let anchor = self.selected.map_or(line, |(anchor, _)| anchor);
+                    self.selected = Some((anchor, line));
+                }
+                None
+            }
+        }
+    }
+
+    pub fn has_selection(&self) -> bool {
+        self.selected.is_some()
+    }
+
+    fn entry(&self, line: usize) -> Option<(PathBuf, bool)> {
+        let entry = self.visible.get(line)?;
+        let root = self.root.as_ref()?;
+        match entry {
+            FileType::Dir(name) => Some((root.join(name), true)),
+            FileType::File(name) => Some((root.join(name), archive::is_archive(Path::new(name)))),
+            FileType::Link { name, is_dir, .. } => Some((root.join(name), *is_dir)),
+        }
+    }
+
+    // The entry under the cursor, with whether it should be browsed like a folder
+    pub fn selected_entry(&self) -> Option<(PathBuf, bool)> {
+        self.entry(self.selected?.1)
+    }
+
+    // Every entry between the selection anchor and the cursor
+    pub fn selected_entries(&self) -> Vec<(PathBuf, bool)> {
+        let Some((anchor, cursor)) = self.selected else {
+            return Vec::new();
+        };
+        (anchor.min(cursor)..=anchor.max(cursor))
+            .filter_map(|line| self.entry(line))
+            .collect()
+    }
+
+    // The archive under the selected entry, or the archive being browsed. Entries are
+    // told apart by how they were listed, so drawing the page doesn't touch the disk
+    pub fn selected_archive(&self) -> Option<PathBuf> {
+        let selected = self.selected.and_then(|(_, line)| self.visible.get(line));
+        if let (Some(root), Some(FileType::File(name) | FileType::Link { name, is_dir: false, .. })) =
+            (&self.root, selected)
+        {
+            if archive::is_archive(Path::new(name)) {
+                return Some(root.join(name));
+            }
+        }
+        self.archive.clone()
+    }
+}
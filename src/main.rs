@@ -1,14 +1,55 @@
+mod archive;
+mod atomic;
+mod checksum;
+mod cli;
+mod compare;
+mod diff;
+mod disk_usage;
+mod duplicates;
+mod filter;
+mod history;
+mod keymap;
+mod links;
+mod listing;
+mod recovery;
+mod rename;
+mod search;
+mod settings;
+mod snapshot;
+mod sync;
+mod tabs;
+mod themes;
 
 use iced::Theme;
 use iced::{
-    executor,
+    event, executor, keyboard,
     widget::{
-        button, column, container, horizontal_space, row, text, text_editor,
-        vertical_space, PickList,
+        button, checkbox, column, container, horizontal_space, progress_bar, row, scrollable,
+        slider, text, text_editor, text_input, vertical_space, PickList,
     },
-    Application, Command, Settings,
+    Application, Command, Event, Settings, Subscription,
 };
-use std::collections::VecDeque;
+use archive::ArchiveFormat;
+use checksum::{Checksums, HashAlgorithm, Outcome};
+use compare::{Comparison, DiffKind};
+use diff::{DiffSide, Source, TextDiff};
+use disk_usage::{DiskUsage, UsageSort};
+use duplicates::Duplicates;
+use filter::{KindFilter, ListingFilter};
+use history::{History, Retention};
+use keymap::{Action, Keymap};
+use links::LinkMode;
+use listing::{Listing, Side};
+use rename::{BulkRename, CaseChange};
+use search::IndexEntry;
+use settings::UserSettings;
+use snapshot::{ChangeKind, Snapshots};
+use sync::{SyncAction, SyncJob, SyncMode};
+use tabs::Tab;
+use themes::ThemeSet;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::time::Duration;
 
 use std::{
     io,
@@ -21,19 +62,109 @@ use tokio::fs;
 enum FileType {
     Dir(String),
     File(String),
+    // is_dir is whether the target is a folder, a broken link counts as a file
+    Link {
+        name: String,
+        target: PathBuf,
+        is_dir: bool,
+    },
+}
+
+impl FileType {
+    fn name(&self) -> &str {
+        match self {
+            FileType::Dir(name) | FileType::File(name) | FileType::Link { name, .. } => name,
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        match self {
+            FileType::Dir(_) => true,
+            FileType::File(_) => false,
+            FileType::Link { is_dir, .. } => *is_dir,
+        }
+    }
+}
+
+// What to open once the recovery dialog shown at startup is dealt with
+#[derive(Debug)]
+struct Startup {
+    files: Vec<PathBuf>,
+    active: Option<usize>,
+    folder: Option<PathBuf>,
+}
+
+impl Startup {
+    fn command(self) -> Command<Message> {
+        let (active, folder) = (self.active, self.folder.clone());
+        if !self.files.is_empty() {
+            Command::perform(read_files(self.files), move |results| {
+                Message::FilesLoaded(results, active, folder)
+            })
+        } else if let Some(folder) = self.folder {
+            Command::perform(read_directory(folder), Message::LoadFolder)
+        } else {
+            Command::none()
+        }
+    }
 }
 
 #[derive(Debug)]
 struct FileSystem {
-    content: text_editor::Content,
     file_content: text_editor::Content,
     dir: Option<PathBuf>,
+    // Whether dir is outside any archive, worked out by update for dir_checked when dir changes
+    dir_checked: Option<PathBuf>,
+    dir_on_disk: bool,
     error: Option<Error>,
     show_menu: bool,
     mode: Mode,
-    modecount: u32,
     theme: Theme,
+    themes: ThemeSet,
     clipboard: Option<PathBuf>,
+    clipboard_cut: bool,
+    // Set with the clipboard, links can only be made to entries outside archives
+    clipboard_on_disk: bool,
+    keymap: Keymap,
+    palette_query: String,
+    recent_actions: Vec<Action>,
+    index: Vec<IndexEntry>,
+    index_root: Option<PathBuf>,
+    indexing: bool,
+    index_error: Option<Error>,
+    quick_query: String,
+    previous_mode: Mode,
+    listing: Listing,
+    other: Listing,
+    active_side: Side,
+    comparison: Option<Comparison>,
+    text_diff: Option<TextDiff>,
+    sync_job: Option<SyncJob>,
+    bulk_rename: Option<BulkRename>,
+    duplicates: Option<Duplicates>,
+    disk_usage: Option<DiskUsage>,
+    checksums: Option<Checksums>,
+    checksum_algorithm: HashAlgorithm,
+    snapshots: Option<Snapshots>,
+    history: Option<History>,
+    history_retention: Retention,
+    swaps: Vec<recovery::Swap>,
+    startup: Option<Startup>,
+    auto_save_on_focus_loss: bool,
+    auto_save_interval: u64,
+    // Set by handlers that change a setting, update saves them afterwards
+    settings_changed: bool,
+    task_status: Option<String>,
+    compress_sources: Vec<PathBuf>,
+    compress_name: String,
+    compress_format: ArchiveFormat,
+    compress_level: u8,
+    verify_copies: bool,
+    follow_links: bool,
+    filter: ListingFilter,
+    tabs: Vec<Tab>,
+    active_tab: Option<usize>,
+    window_size: (f32, f32),
 }
 #[derive(Debug, Clone)]
 enum Mode {
@@ -42,6 +173,21 @@ enum Mode {
     OnDir,
     ConfirmDel,
     ThemePage,
+    QuickOpen,
+    Compress,
+    Shortcuts,
+    Palette,
+    Commander,
+    Compare,
+    Diff,
+    Sync,
+    Rename,
+    Duplicates,
+    DiskUsage,
+    Checksums,
+    History,
+    Recovery,
+    Snapshots,
 }
 
 #[derive(Debug, Clone)]
@@ -51,6 +197,7 @@ enum Message {
     LoadFileFolder(Result<(PathBuf, Vec<FileType>), Error>),
     LoadFile(Result<(PathBuf, Arc<String>), Error>),
     OpenFile,
+    FilePicked(Result<PathBuf, Error>),
     OpenFolder,
     New,
     Show,
@@ -67,8 +214,146 @@ enum Message {
     SelectedTheme(Theme),
     BackFolder,
     Copy,
+    Cut,
     Paste,
     Refresh,
+    QuickOpen,
+    IndexBuilt(Result<(PathBuf, Vec<IndexEntry>), Error>),
+    QuickQuery(String),
+    QuickSubmit,
+    QuickPick(PathBuf, bool),
+    CloseOverlay,
+    Shortcut(keyboard::Key, keyboard::Modifiers),
+    ShowShortcuts,
+    CommandPalette,
+    PaletteQuery(String),
+    PaletteSubmit,
+    RunAction(Action),
+    ToggleHidden(bool),
+    FilterKind(KindFilter),
+    FilterExtensions(String),
+    FilterQuery(String),
+    SwitchTab(usize),
+    FilesLoaded(Vec<Result<(PathBuf, Arc<String>), Error>>, Option<usize>, Option<PathBuf>),
+    SettingsSaved(Result<(), Error>),
+    Pasted(Result<Option<compare::CopyCheck>, Error>),
+    ToggleVerifyCopies,
+    ToggleFollowLinks,
+    PasteLink(bool),
+    LinkCreated(Result<PathBuf, Error>),
+    WindowResized(u32, u32),
+    CloseRequested,
+    Exit,
+    CloseTab(usize),
+    ListingAction(Side, text_editor::Action),
+    DualPane,
+    PaneLoaded(Side, Result<(PathBuf, Vec<FileType>), Error>),
+    PaneUp(Side),
+    CopyToOther,
+    MoveToOther,
+    PaneTransferred(Result<usize, Error>),
+    SwapPanes,
+    CompareFolders,
+    CompareFolderPicked(Result<PathBuf, Error>),
+    FoldersCompared(Result<Vec<compare::DiffEntry>, Error>),
+    CompareContents(bool),
+    Recompare,
+    CopyAcross(Side, String),
+    CopiedAcross(Result<PathBuf, Error>),
+    CompareFiles,
+    CompareWithSaved,
+    DiffSecondPicked(PathBuf, Result<PathBuf, Error>),
+    DiffFilesLoaded(Result<(DiffText, DiffText), Error>),
+    SavedVersionLoaded(Result<DiffText, Error>),
+    DiffComputed(Result<TextDiff, Error>),
+    DiffGoTo(usize),
+    DiffCopyHunk(Side),
+    DiffSave(Side),
+    DiffSaved(Side, Result<PathBuf, Error>),
+    SyncFolders,
+    SyncFolderPicked(Result<PathBuf, Error>),
+    SyncModeSelected(SyncMode),
+    SyncDeleteExtras(bool),
+    SyncPreview,
+    SyncPlanned(Result<Vec<sync::SyncOp>, Error>),
+    RunSync,
+    Synced(Result<usize, Error>),
+    ExtractHere,
+    ExtractTo,
+    ExtractFolderPicked(Result<PathBuf, Error>),
+    Extracting(archive::Progress),
+    Compress,
+    CompressName(String),
+    CompressFormat(ArchiveFormat),
+    CompressLevel(u8),
+    ConfirmCompress,
+    CancelCompress,
+    Compressing(archive::Progress),
+    BulkRename,
+    RenameLoaded(
+        PathBuf,
+        Result<(Vec<rename::RenameSource>, std::collections::BTreeSet<String>), Error>,
+    ),
+    RenameFind(String),
+    RenameReplace(String),
+    RenameRegex(bool),
+    RenamePattern(String),
+    RenameStart(String),
+    RenameCase(CaseChange),
+    ConfirmRename,
+    CancelRename,
+    Renamed(Result<usize, Error>),
+    FindDuplicates,
+    DuplicatesFound(Result<Vec<duplicates::DuplicateGroup>, Error>),
+    KeepDuplicate(usize, usize),
+    TrashDuplicates(usize),
+    LinkDuplicates(usize),
+    DuplicatesResolved(Result<(Vec<PathBuf>, usize), Error>),
+    DiskUsage,
+    DiskUsageScanned(Result<disk_usage::UsageNode, Error>),
+    UsageOpen(String),
+    UsageUp,
+    UsageSortBy(UsageSort),
+    UsageShowFolder,
+    Checksums,
+    ChecksumAlgorithm(HashAlgorithm),
+    ChecksumsHashed(Result<Vec<(PathBuf, String)>, Error>),
+    CopyChecksum(String),
+    WriteManifest,
+    ManifestWritten(Result<(PathBuf, usize), Error>),
+    VerifyManifest,
+    ManifestPicked(Result<PathBuf, Error>),
+    ManifestVerified(Result<Vec<checksum::Verification>, Error>),
+    Snapshots,
+    SnapshotsListed(PathBuf, Result<Vec<snapshot::SnapshotInfo>, Error>),
+    TakeSnapshot,
+    SnapshotTaken(PathBuf, Result<snapshot::SnapshotInfo, Error>),
+    CompareSnapshot(usize),
+    SnapshotCompared(PathBuf, Result<Vec<snapshot::SnapshotChange>, Error>),
+    RestoreChange(usize),
+    RestoreSnapshot,
+    SnapshotRestored(Result<usize, Error>),
+    DeleteSnapshot(usize),
+    SnapshotDeleted(Result<(), Error>),
+    FileHistory,
+    HistoryLoaded(PathBuf, Result<Vec<history::Version>, Error>),
+    HistoryCompare(usize),
+    HistoryRestore(usize),
+    VersionLoaded(history::Version, bool, Result<String, Error>),
+    WriteSwaps,
+    SwapsWritten(Vec<(PathBuf, u64)>, Result<(), Error>),
+    SwapsRemoved,
+    RecoverSwap(usize),
+    CompareSwap(usize),
+    SwapFileLoaded(PathBuf, Result<DiffText, Error>),
+    DiscardSwap(usize),
+    RecoveryDone,
+    FocusLost,
+    AutoSave,
+    AutoSaved(PathBuf, u64, Result<PathBuf, Error>),
+    ToggleAutoSave,
+    CheckThemes,
+    ThemesLoaded(Option<ThemeSet>),
     // BackedFolder(Result<(PathBuf, Vec<FileType>), Error>),
 }
 
@@ -76,230 +361,125 @@ impl Application for FileSystem {
     type Executor = executor::Default;
     type Message = Message;
     type Theme = iced::Theme;
-    type Flags = ();
+    type Flags = (Vec<PathBuf>, UserSettings);
 
-    fn new(flags: Self::Flags) -> (Self, Command<Self::Message>) {
+    // Paths given on the command line win over the saved session, and the folder the app
+    // was started from is listed when there is neither. Files open as tabs and a folder
+    // given next to them is listed beside the editor. Buffers left by a crash are offered
+    // first and the rest waits until they are dealt with
+    fn new((paths, settings): Self::Flags) -> (Self, Command<Self::Message>) {
+        let (folders, mut files, mut active): (Vec<PathBuf>, Vec<PathBuf>, Option<usize>) =
+            if paths.is_empty() && settings.last_folder.is_none() && settings.tabs.is_empty() {
+                (vec![default_file()], Vec::new(), None)
+            } else if paths.is_empty() {
+                let folders = settings.last_folder.iter().cloned().collect();
+                (folders, settings.tabs.clone(), settings.active_tab)
+            } else {
+                let (folders, files) = paths
+                    .into_iter()
+                    .partition(|path| path.is_dir() || archive::is_archive(path));
+                (folders, files, None)
+            };
+        if files.iter().any(|path| !path.exists() && archive::split_archive_path(path).is_none()) {
+            files.retain(|path| path.exists() || archive::split_archive_path(path).is_some());
+            active = None;
+        }
+        let startup = Startup {
+            files,
+            active,
+            folder: folders.into_iter().find(|folder| folder.exists()),
+        };
+        let swaps = recovery::load();
+        let (mode, startup, command) = if swaps.is_empty() {
+            (Mode::Start, None, startup.command())
+        } else {
+            (Mode::Recovery, Some(startup), Command::none())
+        };
+        let themes = themes::load();
+        let filter = ListingFilter {
+            show_hidden: settings.show_hidden,
+            kind: settings.kind_filter(),
+            ..ListingFilter::default()
+        };
         (
             Self {
-                content: text_editor::Content::new(),
                 file_content: text_editor::Content::new(),
                 dir: None,
+                dir_checked: None,
+                dir_on_disk: false,
                 error: None,
                 show_menu: false,
-                mode: Mode::Start,
-                modecount: 0,
-                theme: Theme::Dark,
+                mode,
+                theme: settings.theme(&themes).unwrap_or(Theme::Dark),
+                themes,
                 clipboard: None,
+                clipboard_cut: false,
+                clipboard_on_disk: false,
+                keymap: keymap::load(),
+                palette_query: String::new(),
+                recent_actions: settings.recent_actions(),
+                index: Vec::new(),
+                index_root: None,
+                indexing: false,
+                index_error: None,
+                quick_query: String::new(),
+                previous_mode: Mode::Start,
+                listing: Listing::default(),
+                other: Listing::default(),
+                active_side: Side::Left,
+                comparison: None,
+                text_diff: None,
+                sync_job: None,
+                bulk_rename: None,
+                duplicates: None,
+                disk_usage: None,
+                checksums: None,
+                checksum_algorithm: HashAlgorithm::Sha256,
+                snapshots: None,
+                history: None,
+                history_retention: settings.retention(),
+                swaps,
+                startup,
+                auto_save_on_focus_loss: settings.auto_save_on_focus_loss,
+                auto_save_interval: settings.auto_save_interval,
+                settings_changed: false,
+                task_status: None,
+                compress_sources: Vec::new(),
+                compress_name: String::new(),
+                compress_format: ArchiveFormat::Zip,
+                compress_level: settings.compress_level.min(9),
+                verify_copies: settings.verify_copies,
+                follow_links: settings.follow_links,
+                filter,
+                tabs: Vec::new(),
+                active_tab: None,
+                window_size: (settings.window_width, settings.window_height),
             },
-            Command::perform(read_directory(default_file()), Message::LoadFolder),
+            command,
         )
     }
 
     fn title(&self) -> String {
-        format!("File System")
+        "File System".to_string()
     }
 
+    // Handlers that change a setting mark it, and the settings are then saved once for the
+    // whole message. The window size is only saved on exit. Whether the folder is inside an
+    // archive is checked here once it changes, not every time a page is drawn
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
-        match message {
-            Message::Edit(action) => self.file_content.perform(action),
-            Message::LoadFile(result) => {
-                self.mode = Mode::Opened;
-                if let Ok((path, content)) = result {
-                    self.mode = Mode::Opened;
-                    self.dir = Some(path.clone());
-                    self.file_content = text_editor::Content::with_text(&content);
-                    println!("File loaded successfully");
-                    return Command::perform(read_file_directory(path), Message::LoadFileFolder);
-                } else {
-                    self.error = result.err()
-                }
-            }
-            Message::GoDirPage => {
-                self.mode = Mode::OnDir;
-                let mut file_path = self.dir.clone().unwrap();
-                file_path.pop();
-                self.dir = Some(file_path);
-                self.show_menu = false;
-                return Command::none();
-            }
-            Message::LoadFolder(result) => {
-                if self.modecount == 0 {
-                    println!("Mode Count{}", self.modecount);
-                    self.modecount += 1;
-                    return Command::none();
-                } else {
-                    self.mode = Mode::OnDir;
-                    println!("Mode Count{}", self.modecount);
-                    let mut text = String::new();
-                    if let Ok(folder) = result {
-                        self.dir = Some(folder.0);
-                        for filename in folder.1 {
-                            match filename {
-                                FileType::Dir(var) => {
-                                    text.push_str(&format!("📁{}\n", var));
-                                }
-                                FileType::File(var) => {
-                                    text.push_str(&format!("📝{}\n", var));
-                                }
-                            }
-                        }
-
-                        self.file_content = text_editor::Content::new();
-                        self.content = text_editor::Content::with_text(&text);
-                    }
-                }
-            }
-            Message::LoadFileFolder(result) => {
-                let mut text = String::new();
-                println!("Changing File/Folder");
-                if let Ok(folder) = result {
-                    for filename in folder.1 {
-                        match filename {
-                            FileType::Dir(var) => {
-                                text.push_str(&format!("📁{}\n", var));
-                            }
-                            FileType::File(var) => {
-                                text.push_str(&format!("📝{}\n", var));
-                            }
-                        }
-                    }
-                    self.content = text_editor::Content::with_text(&text);
-                }
-            }
-            Message::BackFolder => {
-                let mut file_path = self.dir.clone().unwrap();
-                file_path.pop();
-                self.dir = Some(file_path);
-                return Command::perform(
-                    read_directory(self.dir.clone().unwrap()),
-                    Message::LoadFolder,
-                );
-            }
-            Message::New => {
-                self.mode = Mode::Opened;
-                self.file_content = text_editor::Content::new();
-                self.show_menu = false;
-                let text = self.file_content.text();
-                if self.dir.is_none() {
-                    self.dir = Some(default_file());
-                    return Command::perform(save_file(self.dir.clone(), text), Message::FileSaved);
-                }
-                if !self.dir.clone().unwrap().is_dir() {
-                    let mut file_path = self.dir.clone().unwrap();
-                    file_path.pop();
-                    self.dir = Some(file_path);
-                    return Command::perform(save_file(self.dir.clone(), text), Message::FileSaved);
-                } else {
-                    return Command::perform(save_file(self.dir.clone(), text), Message::FileSaved);
-                }
-            }
-            Message::Show => {
-                self.show_menu = !self.show_menu;
-            }
-            Message::Save => {
-                let text = self.file_content.text();
-                self.show_menu = false;
-                return Command::perform(save_file(self.dir.clone(), text), Message::FileSaved);
-            }
-            Message::FileSaved(result) => {
-                if let Err(error) = result {
-                    self.error = Some(error);
-                } else {
-                    self.dir = Some(result.unwrap());
-                    return Command::perform(
-                        read_file_directory(self.dir.clone().unwrap()),
-                        Message::LoadFileFolder,
-                    );
-                }
-            }
-            Message::Delete => {
-                self.mode = Mode::ConfirmDel;
-                return Command::none();
-            }
-            Message::ConfirmDelete => {
-                return Command::perform(delete_file(self.dir.clone()), Message::FileDeleted)
-            }
-            Message::FileDeleted(result) => {
-                if let Err(error) = result {
-                    self.error = Some(error);
-                } else {
-                    self.file_content = text_editor::Content::new();
-                    self.mode = Mode::OnDir;
-                    self.show_menu = false;
-                    return Command::perform(
-                        read_file_directory(self.dir.clone().unwrap()),
-                        Message::LoadFileFolder,
-                    );
-                }
-            }
-            Message::CreateFolder => {
-                return Command::perform(create_folder(self.dir.clone()), Message::CreatedFolder)
-            }
-            Message::CreatedFolder(result) => {
-                if let Err(error) = result {
-                    self.error = Some(error);
-                } else {
-                    self.dir = Some(result.unwrap());
-                    return Command::perform(
-                        read_file_directory(self.dir.clone().unwrap()),
-                        Message::LoadFileFolder,
-                    );
-                }
-            }
-            Message::GoEditPage => {
-                self.mode = Mode::Opened;
-                return Command::none();
-            }
-            Message::GoThemePage => {
-                self.mode = Mode::ThemePage;
-                return Command::none();
-            }
-            Message::Copy => {
-                self.clipboard = self.dir.clone();
-                println!("Copied {:?}", self.clipboard);
-                return Command::none();
-            }
-            Message::Paste => {
-                if let Some(og_path) = self.clipboard.clone() {
-                    if let Some(dest_path) = self.dir.clone() {
-                        if og_path.is_dir() {
-                            return Command::perform(
-                                async move {
-                                    copy_foldder(&og_path, &dest_path)
-                                        .await
-                                        .map(|_| (dest_path.clone(), vec![]))
-                                },
-                                Message::LoadFileFolder,
-                            );
-                        } else {
-                            return Command::perform(
-                                async move {
-                                    let og_path_clone = og_path.clone();
-                                    copy_file(&og_path_clone, &dest_path)
-                                        .await
-                                        .map(|_| (dest_path.clone(), vec![]))
-                                },
-                                Message::LoadFileFolder,
-                            );
-                        }
-                    }
-                }
-            }
-            Message::Refresh => {
-                return Command::perform(
-                    read_directory(self.dir.clone().unwrap()),
-                    Message::LoadFolder,
-                )
-            }
-            Message::SelectedTheme(th) => {
-                self.theme = th;
-                self.mode = Mode::Opened;
-                return Command::none();
-            }
-            Message::OpenFile => return Command::perform(open_file(), Message::LoadFile),
-            Message::OpenFolder => return Command::perform(open_folder(), Message::LoadFolder),
+        let command = self.handle_message(message);
+        if self.dir_checked != self.dir {
+            self.dir_on_disk = self
+                .dir
+                .as_deref()
+                .is_some_and(|dir| archive::split_archive_path(dir).is_none());
+            self.dir_checked = self.dir.clone();
         }
-        Command::none()
+        if !std::mem::take(&mut self.settings_changed) {
+            return command;
+        }
+        let save = Command::perform(settings::save(self.current_settings()), Message::SettingsSaved);
+        Command::batch([command, save])
     }
     fn view(&self) -> iced::Element<'_, Self::Message> {
         match self.mode {
@@ -332,8 +512,9 @@ impl Application for FileSystem {
             }
             Mode::Opened => {
                 let txt = text("Files System  ||     ");
-                let txt_edit = text_editor(&self.content)
-                    .on_action(Message::Edit)
+                let txt_edit = text_editor(&self.listing.content)
+                    .on_action(|action| Message::ListingAction(Side::Left, action))
+                    .style(self.editor_style())
                     .height(1080);
 
                 let pathh: &str = self
@@ -355,6 +536,8 @@ impl Application for FileSystem {
                 let space = text("    ");
                 let delete_button = button("Delete").on_press(Message::Delete);
                 let change_theme = button("Change Theme").on_press(Message::GoThemePage);
+                let history_button = button("History")
+                    .on_press_maybe(self.active_tab.map(|_| Message::FileHistory));
 
                 if self.show_menu {
                     let toprow = row![
@@ -368,6 +551,8 @@ impl Application for FileSystem {
                         save_button,
                         space.clone(),
                         delete_button,
+                        space.clone(),
+                        history_button,
                         horizontal_space(),
                         change_theme,
                         space.clone(),
@@ -382,13 +567,14 @@ impl Application for FileSystem {
                     .padding(5);
                     let txt_content = text_editor(&self.file_content)
                         .on_action(Message::Edit)
+                        .style(self.editor_style())
                         .height(1000);
 
                     let container1 = container(column![txt_edit]).padding(10).width(200);
 
                     let container2 = container(column![txt_content]).padding(10);
 
-                    column![toprow, row![container1, container2]].into()
+                    column![toprow, self.tab_bar(), row![container1, container2]].into()
                 } else {
                     let toprow = row![
                         txt,
@@ -404,20 +590,22 @@ impl Application for FileSystem {
                     .padding(5);
                     let txt_content = text_editor(&self.file_content)
                         .on_action(Message::Edit)
+                        .style(self.editor_style())
                         .height(1000);
 
                     let container1 = container(column![txt_edit]).padding(10).width(200);
 
                     let container2 = container(column![txt_content]).padding(10);
 
-                    column![toprow, row![container1, container2]].into()
+                    column![toprow, self.tab_bar(), row![container1, container2]].into()
                 }
             }
             Mode::OnDir => {
                 let vspace = text("\n");
                 let hspace = text("    ");
-                let txt_edit = text_editor(&self.content)
-                    .on_action(Message::Edit)
+                let txt_edit = text_editor(&self.listing.content)
+                    .on_action(|action| Message::ListingAction(Side::Left, action))
+                    .style(self.editor_style())
                     .height(1080);
                 let pathh: &str = self
                     .dir
@@ -445,6 +633,37 @@ impl Application for FileSystem {
                 )
                 .on_press(Message::BackFolder)
                 .width(410);
+                let can_extract = self.listing.selected_archive().is_some();
+                let extract_here_button = button(
+                    text("Extract Here")
+                        .horizontal_alignment(iced::alignment::Horizontal::Center)
+                        .size(35),
+                )
+                .on_press_maybe(can_extract.then_some(Message::ExtractHere))
+                .width(200);
+                let extract_to_button = button(
+                    text("Extract To")
+                        .horizontal_alignment(iced::alignment::Horizontal::Center)
+                        .size(35),
+                )
+                .on_press_maybe(can_extract.then_some(Message::ExtractTo))
+                .width(200);
+                let can_compress = self.can_compress();
+                let compress_button = button(
+                    text("Compress")
+                        .horizontal_alignment(iced::alignment::Horizontal::Center)
+                        .size(35),
+                )
+                .on_press_maybe(can_compress.then_some(Message::Compress))
+                .width(200);
+                let dual_pane_button = button(
+                    text("Dual Pane")
+                        .horizontal_alignment(iced::alignment::Horizontal::Center)
+                        .size(35),
+                )
+                .on_press(Message::DualPane)
+                .width(200);
+                let status_text = text(self.task_status.as_deref().unwrap_or(""));
                 let all_button = column![
                     vertical_space(),
                     row![new_file_button, hspace.clone(), new_folder_button],
@@ -452,7 +671,11 @@ impl Application for FileSystem {
                     row![select_file_button, hspace.clone(), select_folder_button],
                     vspace.clone(),
                     row![exit_button],
-                    
+                    vspace.clone(),
+                    row![extract_here_button, hspace.clone(), extract_to_button],
+                    vspace.clone(),
+                    row![compress_button, hspace.clone(), dual_pane_button],
+                    status_text,
                     vertical_space(),
                     row![
                         paste_button,
@@ -464,7 +687,9 @@ impl Application for FileSystem {
                     vspace.clone()
                     
                 ];
-                let container1 = container(column![txt_edit]).padding(10).width(700);
+                let container1 = container(column![self.filter_row(), txt_edit].spacing(10))
+                    .padding(10)
+                    .width(700);
                 let container2 =
                     container(row![horizontal_space(), all_button, horizontal_space()]).padding(10);
                 column![toprow, row![container1, container2]].into()
@@ -495,9 +720,24 @@ impl Application for FileSystem {
                 .padding(50)
                 .into()
             }
+            Mode::QuickOpen => self.quick_open_view(),
+            Mode::Compress => self.compress_view(),
+            Mode::Rename => self.rename_view(),
+            Mode::Duplicates => self.duplicates_view(),
+            Mode::DiskUsage => self.disk_usage_view(),
+            Mode::Checksums => self.checksums_view(),
+            Mode::History => self.history_view(),
+            Mode::Recovery => self.recovery_view(),
+            Mode::Snapshots => self.snapshots_view(),
+            Mode::Shortcuts => self.shortcuts_view(),
+            Mode::Palette => self.palette_view(),
+            Mode::Commander => self.commander_view(),
+            Mode::Compare => self.compare_view(),
+            Mode::Diff => self.diff_view(),
+            Mode::Sync => self.sync_view(),
             Mode::ThemePage => {
                 let picklist =
-                    PickList::new(Theme::ALL, Some(self.theme.clone()), Message::SelectedTheme);
+                    PickList::new(self.themes.all(), Some(self.theme.clone()), Message::SelectedTheme);
 
                 container(row![horizontal_space(),column![vertical_space(),picklist,vertical_space()],horizontal_space()]).padding(50).into()
             }
@@ -507,99 +747,3086 @@ impl Application for FileSystem {
     fn theme(&self) -> Self::Theme {
         self.theme.clone()
     }
-}
-
-#[derive(Debug, Clone)]
-enum Error {
-    DClosed,
-    IOErr(io::ErrorKind),
-}
-
-fn default_file() -> PathBuf {
-    PathBuf::from(format!("{}", env!("CARGO_MANIFEST_DIR")))
-}
-
-async fn read_file(path: PathBuf) -> Result<(PathBuf, Arc<String>), Error> {
-    let content = tokio::fs::read_to_string(&path)
-        .await
-        .map(Arc::new)
-        .map_err(|error| error.kind())
-        .map_err(Error::IOErr)?;
-
-    Ok((path, content))
-}
-
-async fn read_directory(path: PathBuf) -> Result<(PathBuf, Vec<FileType>), Error> {
-    println!("Reading directory: {:?}", path);
-    let mut handle = tokio::fs::read_dir(&path)
-        .await
-        .map_err(|error| error.kind())
-        .map_err(Error::IOErr)?;
-
-    let mut fsname = Vec::new();
-    while let Some(file) = handle.next_entry().await.unwrap_or(None) {
-        let file_name = file.file_name();
-        let metadata = file.metadata().await.unwrap();
-        if metadata.is_dir() {
-            fsname.push(FileType::Dir(file_name.to_string_lossy().to_string()))
-        } else {
-            fsname.push(FileType::File(file_name.to_string_lossy().to_string()));
-        }
-    }
-
-    Ok((path, fsname))
-}
-
-async fn read_file_directory(path: PathBuf) -> Result<(PathBuf, Vec<FileType>), Error> {
-    let mut file_path = path.clone();
-    file_path.pop();
-    println!("Reading directory: {:?}", file_path);
-    let mut handle = tokio::fs::read_dir(&file_path)
-        .await
-        .map_err(|error| error.kind())
-        .map_err(Error::IOErr)?;
 
-    let mut fsname = Vec::new();
-    while let Some(file) = handle.next_entry().await.unwrap_or(None) {
-        let file_name = file.file_name();
-        let metadata = file.metadata().await.unwrap();
-        if metadata.is_dir() {
-            fsname.push(FileType::Dir(file_name.to_string_lossy().to_string()))
+    fn subscription(&self) -> Subscription<Self::Message> {
+        let auto_save = if self.auto_save_interval > 0 {
+            iced::time::every(Duration::from_secs(self.auto_save_interval)).map(|_| Message::AutoSave)
         } else {
-            fsname.push(FileType::File(file_name.to_string_lossy().to_string()));
-        }
+            Subscription::none()
+        };
+        Subscription::batch([
+            event::listen_with(handle_event),
+            iced::time::every(Duration::from_secs(2)).map(|_| Message::CheckThemes),
+            iced::time::every(SWAP_INTERVAL).map(|_| Message::WriteSwaps),
+            auto_save,
+        ])
     }
-
-    Ok((file_path, fsname))
 }
 
-async fn create_folder(path: Option<PathBuf>) -> Result<PathBuf, Error> {
-    let path = if let Some(path) = path {
-        if path.is_dir() {
-            rfd::AsyncFileDialog::new()
-                .set_title("Choose Folder Name")
-                .save_file()
-                .await
-                .ok_or(Error::DClosed)?
-                .path()
-                .to_owned()
-        } else {
-            path
-        }
-    } else {
-        rfd::AsyncFileDialog::new()
-            .set_title("Choost Folder Name")
-            .save_file()
-            .await
-            .ok_or(Error::DClosed)?
-            .path()
-            .to_owned()
-    };
-    println!("Creating Folder");
-    tokio::fs::create_dir(&path)
-        .await
-        .map_err(|error| error.kind())
-        .map_err(Error::IOErr)?;
+impl FileSystem {
+    fn handle_message(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Edit(action) => {
+                if action.is_edit() {
+                    if let Some(index) = self.active_tab {
+                        self.tabs[index].revision += 1;
+                    }
+                }
+                self.file_content.perform(action);
+            }
+            Message::LoadFile(result) => {
+                self.mode = Mode::Opened;
+                if let Ok((path, content)) = result {
+                    self.mode = Mode::Opened;
+                    self.dir = Some(path.clone());
+                    self.open_tab(path.clone(), &content);
+                    println!("File loaded successfully");
+                    return Command::perform(read_file_directory(path), Message::LoadFileFolder);
+                } else {
+                    self.error = result.err()
+                }
+            }
+            Message::GoDirPage => {
+                self.mode = Mode::OnDir;
+                let mut file_path = self.dir.clone().unwrap();
+                file_path.pop();
+                self.dir = Some(file_path);
+                self.show_menu = false;
+                return Command::none();
+            }
+            Message::LoadFolder(result) => {
+                self.mode = Mode::OnDir;
+                match result {
+                    Ok(folder) => {
+                        self.dir = Some(folder.0.clone());
+                        self.park_active_tab();
+                        self.settings_changed |= self.listing.root.as_ref() != Some(&folder.0);
+                        self.listing.show(folder.0, folder.1, &self.filter);
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::LoadFileFolder(result) => {
+                println!("Changing File/Folder");
+                if let Ok(folder) = result {
+                    self.settings_changed |= self.listing.root.as_ref() != Some(&folder.0);
+                    self.listing.show(folder.0, folder.1, &self.filter);
+                }
+            }
+            Message::BackFolder => {
+                let mut file_path = self.dir.clone().unwrap();
+                file_path.pop();
+                self.dir = Some(file_path);
+                return Command::perform(
+                    read_directory(self.dir.clone().unwrap()),
+                    Message::LoadFolder,
+                );
+            }
+            Message::New => {
+                self.mode = Mode::Opened;
+                self.park_active_tab();
+                self.show_menu = false;
+                let text = self.file_content.text();
+                if self.dir.is_none() {
+                    self.dir = Some(default_file());
+                    return Command::perform(
+                        save_file(self.dir.clone(), text, self.history_retention),
+                        Message::FileSaved,
+                    );
+                }
+                if !self.dir.clone().unwrap().is_dir() {
+                    let mut file_path = self.dir.clone().unwrap();
+                    file_path.pop();
+                    self.dir = Some(file_path);
+                    return Command::perform(
+                        save_file(self.dir.clone(), text, self.history_retention),
+                        Message::FileSaved,
+                    );
+                } else {
+                    return Command::perform(
+                        save_file(self.dir.clone(), text, self.history_retention),
+                        Message::FileSaved,
+                    );
+                }
+            }
+            Message::Show => {
+                self.show_menu = !self.show_menu;
+            }
+            Message::Save => {
+                let text = self.file_content.text();
+                self.show_menu = false;
+                return Command::perform(
+                    save_file(self.dir.clone(), text, self.history_retention),
+                    Message::FileSaved,
+                );
+            }
+            Message::FileSaved(result) => {
+                self.index_root = None;
+                if let Err(error) = result {
+                    self.error = Some(error);
+                } else {
+                    let path = result.unwrap();
+                    match self.active_tab {
+                        Some(index) => {
+                            let tab = &mut self.tabs[index];
+                            tab.path = path.clone();
+                            tab.saved = tab.revision;
+                            self.settings_changed = true;
+                        }
+                        None => {
+                            self.tabs
+                                .push(Tab::new(path.clone(), text_editor::Content::new()));
+                            self.active_tab = Some(self.tabs.len() - 1);
+                            self.settings_changed = true;
+                        }
+                    }
+                    self.dir = Some(path.clone());
+                    return Command::batch([
+                        self.remove_swaps(vec![path]),
+                        Command::perform(
+                            read_file_directory(self.dir.clone().unwrap()),
+                            Message::LoadFileFolder,
+                        ),
+                    ]);
+                }
+            }
+            Message::Delete => {
+                self.mode = Mode::ConfirmDel;
+                return Command::none();
+            }
+            Message::ConfirmDelete => {
+                return Command::perform(delete_file(self.dir.clone()), Message::FileDeleted)
+            }
+            Message::FileDeleted(result) => {
+                self.index_root = None;
+                if let Err(error) = result {
+                    self.error = Some(error);
+                } else {
+                    if let Some(index) = self.active_tab {
+                        self.close_tab(index);
+                    }
+                    self.file_content = text_editor::Content::new();
+                    self.mode = Mode::OnDir;
+                    self.show_menu = false;
+                    return Command::perform(
+                        read_file_directory(self.dir.clone().unwrap()),
+                        Message::LoadFileFolder,
+                    );
+                }
+            }
+            Message::CreateFolder => {
+                return Command::perform(create_folder(self.dir.clone()), Message::CreatedFolder)
+            }
+            Message::CreatedFolder(result) => {
+                self.index_root = None;
+                if let Err(error) = result {
+                    self.error = Some(error);
+                } else {
+                    self.dir = Some(result.unwrap());
+                    return Command::perform(
+                        read_file_directory(self.dir.clone().unwrap()),
+                        Message::LoadFileFolder,
+                    );
+                }
+            }
+            Message::GoEditPage => {
+                self.mode = Mode::Opened;
+                return Command::none();
+            }
+            Message::GoThemePage => {
+                self.mode = Mode::ThemePage;
+                return Command::none();
+            }
+            Message::Copy => {
+                self.clipboard = self.listing.selected_entry().map(|(path, _)| path).or(self.dir.clone());
+                self.clipboard_cut = false;
+                self.clipboard_on_disk = self
+                    .clipboard
+                    .as_deref()
+                    .is_some_and(|path| archive::split_archive_path(path).is_none());
+                println!("Copied {:?}", self.clipboard);
+                return Command::none();
+            }
+            Message::Cut => {
+                self.clipboard = self.listing.selected_entry().map(|(path, _)| path).or(self.dir.clone());
+                // Nothing can be removed from inside an archive, so cutting there just copies
+                self.clipboard_on_disk = self
+                    .clipboard
+                    .as_deref()
+                    .is_some_and(|path| archive::split_archive_path(path).is_none());
+                self.clipboard_cut = self.clipboard_on_disk;
+            }
+            Message::Paste => {
+                if let Some(og_path) = self.clipboard.clone() {
+                    if let Some(dest_path) = self.dir.clone() {
+                        // A cut entry is moved once, after that there is nothing left to paste
+                        let cut = std::mem::take(&mut self.clipboard_cut);
+                        if cut {
+                            self.clipboard = None;
+                        }
+                        let verify = self.verify_copies;
+                        let links = self.link_mode();
+                        return Command::perform(
+                            async move {
+                                if cut {
+                                    move_into(&og_path, &dest_path, verify, links).await?;
+                                    return Ok(None);
+                                }
+                                let (_, check) =
+                                    paste_checked(&og_path, &dest_path, verify, links).await?;
+                                Ok(check)
+                            },
+                            Message::Pasted,
+                        );
+                    }
+                }
+            }
+            Message::Pasted(result) => {
+                match result {
+                    Ok(Some(check)) if check.mismatched.is_empty() => {
+                        self.task_status = Some(format!("Verified {} copied files", check.checked));
+                    }
+                    Ok(Some(check)) => {
+                        self.task_status = Some(format!(
+                            "{} of {} copied files don't match: {}",
+                            check.mismatched.len(),
+                            check.checked,
+                            check.mismatched.join(", ")
+                        ));
+                        self.error = Some(Error::IOErr(io::ErrorKind::InvalidData));
+                    }
+                    Ok(None) => {}
+                    Err(error) => self.error = Some(error),
+                }
+                // Even a failed paste may have copied some entries before it stopped
+                return self.reload_listing();
+            }
+            Message::ToggleVerifyCopies => {
+                self.verify_copies = !self.verify_copies;
+                self.settings_changed = true;
+                self.task_status = Some(
+                    if self.verify_copies {
+                        "Copies are verified"
+                    } else {
+                        "Copies are no longer verified"
+                    }
+                    .to_string(),
+                );
+            }
+            Message::ToggleFollowLinks => {
+                self.follow_links = !self.follow_links;
+                self.settings_changed = true;
+                self.task_status = Some(
+                    if self.follow_links {
+                        "Copies follow symlinks"
+                    } else {
+                        "Symlinks are copied as links"
+                    }
+                    .to_string(),
+                );
+            }
+            // Links to the copied or cut entry, which stays on the clipboard
+            Message::PasteLink(hard) => {
+                if let (Some(target), Some(folder)) = (self.clipboard.clone(), self.dir.clone()) {
+                    return Command::perform(
+                        async move {
+                            if hard {
+                                links::hard_link_into(&target, &folder).await
+                            } else {
+                                links::symlink_into(&target, &folder).await
+                            }
+                        },
+                        Message::LinkCreated,
+                    );
+                }
+            }
+            Message::LinkCreated(result) => match result {
+                Ok(link) => {
+                    self.task_status = Some(format!("Created {}", link.display()));
+                    return self.reload_listing();
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::Refresh => {
+                self.index_root = None;
+                return Command::perform(
+                    read_directory(self.dir.clone().unwrap()),
+                    Message::LoadFolder,
+                )
+            }
+            Message::SelectedTheme(th) => {
+                self.theme = th;
+                self.settings_changed = true;
+                self.mode = Mode::Opened;
+                return Command::none();
+            }
+            Message::CheckThemes => {
+                return Command::perform(
+                    themes::reload(self.themes.stamp.clone()),
+                    Message::ThemesLoaded,
+                );
+            }
+            Message::ThemesLoaded(Some(themes)) => {
+                // A theme in use is swapped for its edited version so changes show up live
+                if let Some(custom) = themes.find(&self.theme.to_string()) {
+                    self.theme = custom.theme.clone();
+                }
+                self.themes = themes;
+            }
+            Message::ThemesLoaded(None) => {}
+            Message::QuickOpen => {
+                if let Mode::QuickOpen = self.mode {
+                    return Command::none();
+                }
+                self.previous_mode = self.mode.clone();
+                self.mode = Mode::QuickOpen;
+                self.quick_query.clear();
+                let focus = text_input::focus(quick_open_id());
+                if let Some(root) = self.current_root() {
+                    if self.index_root.as_ref() != Some(&root) && !self.indexing {
+                        self.indexing = true;
+                        self.index_error = None;
+                        return Command::batch([
+                            focus,
+                            Command::perform(search::build_index(root), Message::IndexBuilt),
+                        ]);
+                    }
+                }
+                return focus;
+            }
+            Message::IndexBuilt(result) => {
+                self.indexing = false;
+                match result {
+                    Ok((root, entries)) => {
+                        self.index_root = Some(root);
+                        self.index = entries;
+                    }
+                    Err(error) => self.index_error = Some(error),
+                }
+            }
+            Message::QuickQuery(query) => {
+                self.quick_query = query;
+            }
+            Message::QuickSubmit => {
+                if let Some(entry) = search::search(&self.index, &self.quick_query, 1).first() {
+                    return self.handle_message(Message::QuickPick(entry.path.clone(), entry.is_dir));
+                }
+            }
+            Message::QuickPick(path, is_dir) => {
+                self.mode = self.previous_mode.clone();
+                return open_entry(path, is_dir);
+            }
+            Message::CloseOverlay => {
+                // Only opened from the editor, and a diff opened from it comes back to it
+                if let Mode::History = self.mode {
+                    self.mode = Mode::Opened;
+                } else if let Mode::QuickOpen
+                | Mode::Shortcuts
+                | Mode::Palette
+                | Mode::Compare
+                | Mode::Diff
+                | Mode::Sync
+                | Mode::Duplicates
+                | Mode::DiskUsage
+                | Mode::Checksums
+                | Mode::Snapshots = self.mode
+                {
+                    self.mode = self.previous_mode.clone();
+                }
+            }
+            Message::ShowShortcuts => {
+                self.previous_mode = self.mode.clone();
+                self.mode = Mode::Shortcuts;
+            }
+            Message::Shortcut(key, modifiers) => {
+                let message = self
+                    .keymap
+                    .action(&key, modifiers)
+                    .and_then(|action| self.action_message(action, &self.mode));
+                if let Some(message) = message {
+                    return self.handle_message(message);
+                }
+            }
+            Message::CommandPalette => {
+                self.previous_mode = self.mode.clone();
+                self.mode = Mode::Palette;
+                self.palette_query.clear();
+                return text_input::focus(palette_id());
+            }
+            Message::PaletteQuery(query) => {
+                self.palette_query = query;
+            }
+            Message::PaletteSubmit => {
+                if let Some(action) = self.palette_actions().first() {
+                    return self.handle_message(Message::RunAction(*action));
+                }
+            }
+            Message::RunAction(action) => {
+                // The palette closes first so the action sees the page it was opened from
+                self.mode = self.previous_mode.clone();
+                self.recent_actions.retain(|recent| *recent != action);
+                self.recent_actions.insert(0, action);
+                self.recent_actions.truncate(RECENT_ACTIONS);
+                self.settings_changed = true;
+                if let Some(message) = self.action_message(action, &self.mode) {
+                    return self.handle_message(message);
+                }
+            }
+            Message::ToggleHidden(show) => {
+                self.filter.show_hidden = show;
+                self.settings_changed = true;
+                self.render_listing();
+            }
+            Message::FilterKind(kind) => {
+                self.filter.kind = kind;
+                self.settings_changed = true;
+                self.render_listing();
+            }
+            Message::FilterExtensions(extensions) => {
+                self.filter.extensions = extensions;
+                self.render_listing();
+            }
+            Message::FilterQuery(query) => {
+                self.filter.query = query;
+                self.render_listing();
+            }
+            Message::ListingAction(side, action) => {
+                self.active_side = side;
+                let opened = match side {
+                    Side::Left => self.listing.perform(action),
+                    Side::Right => self.other.perform(action),
+                };
+                if let Some((path, is_dir)) = opened {
+                    // The commander browses folders inside the pane, files still open in the editor
+                    if is_dir && matches!(self.mode, Mode::Commander) {
+                        return Command::perform(read_directory(path), move |result| {
+                            Message::PaneLoaded(side, result)
+                        });
+                    }
+                    return open_entry(path, is_dir);
+                }
+            }
+            Message::DualPane => {
+                if let Mode::Commander = self.mode {
+                    self.mode = Mode::OnDir;
+                    return Command::none();
+                }
+                self.mode = Mode::Commander;
+                self.active_side = Side::Left;
+                // The second pane starts in the parent folder the first time it is shown, so
+                // F5 and F6 have somewhere else to go
+                if self.other.root.is_none() {
+                    if let Some(root) = self.listing.root.clone() {
+                        let root = root.parent().map_or(root.clone(), Path::to_path_buf);
+                        return Command::perform(read_directory(root), |result| {
+                            Message::PaneLoaded(Side::Right, result)
+                        });
+                    }
+                }
+            }
+            Message::PaneLoaded(side, result) => match result {
+                Ok((root, entries)) => {
+                    match side {
+                        Side::Left => {
+                            self.dir = Some(root.clone());
+                            self.settings_changed |= self.listing.root.as_ref() != Some(&root);
+                            self.listing.show(root, entries, &self.filter);
+                        }
+                        Side::Right => self.other.show(root, entries, &self.filter),
+                    }
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::PaneUp(side) => {
+                self.active_side = side;
+                if let Some(parent) = self.pane(side).root.as_deref().and_then(Path::parent) {
+                    return Command::perform(read_directory(parent.to_path_buf()), move |result| {
+                        Message::PaneLoaded(side, result)
+                    });
+                }
+            }
+            Message::CopyToOther => return self.transfer_to_other(false),
+            Message::MoveToOther => return self.transfer_to_other(true),
+            Message::PaneTransferred(result) => {
+                self.index_root = None;
+                match result {
+                    Ok(count) => self.task_status = Some(format!("Transferred {} entries", count)),
+                    Err(error) => {
+                        self.task_status = Some(format!("Transfer failed: {}", error));
+                        self.error = Some(error);
+                    }
+                }
+                // Even a failed transfer may have copied some entries before it stopped
+                return self.reload_panes();
+            }
+            Message::SwapPanes => {
+                std::mem::swap(&mut self.listing, &mut self.other);
+                self.active_side = self.active_side.other();
+                self.dir = self.listing.root.clone();
+            }
+            // The commander compares its two panes, the folder page asks for the second folder
+            Message::CompareFolders => {
+                if let Mode::Commander = self.mode {
+                    if let (Some(left), Some(right)) =
+                        (self.listing.root.clone(), self.other.root.clone())
+                    {
+                        return self.start_compare(left, right);
+                    }
+                }
+                return Command::perform(pick_folder(), Message::CompareFolderPicked);
+            }
+            Message::CompareFolderPicked(result) => match (result, self.listing.root.clone()) {
+                (Ok(right), Some(left)) => return self.start_compare(left, right),
+                (Err(error), _) => self.error = Some(error),
+                _ => {}
+            },
+            Message::FoldersCompared(result) => {
+                if let Some(comparison) = &mut self.comparison {
+                    comparison.running = false;
+                    match result {
+                        Ok(entries) => comparison.entries = entries,
+                        Err(error) => {
+                            comparison.entries.clear();
+                            self.error = Some(error);
+                        }
+                    }
+                }
+            }
+            Message::CompareContents(contents) => {
+                if let Some(comparison) = &mut self.comparison {
+                    comparison.contents = contents;
+                }
+                return self.recompare();
+            }
+            Message::Recompare => return self.recompare(),
+            Message::CopyAcross(from, relative) => {
+                if let Some(comparison) = &self.comparison {
+                    return Command::perform(
+                        compare::copy_across(
+                            comparison.root(from).to_path_buf(),
+                            comparison.root(from.other()).to_path_buf(),
+                            relative,
+                            self.link_mode(),
+                        ),
+                        Message::CopiedAcross,
+                    );
+                }
+            }
+            Message::CopiedAcross(result) => {
+                self.index_root = None;
+                if let Err(error) = result {
+                    self.error = Some(error);
+                }
+                return self.recompare();
+            }
+            // Two selected files, or one and a file picked after it. The commander takes the
+            // file under the cursor in each pane
+            Message::CompareFiles => {
+                let files: Vec<PathBuf> = if let Mode::Commander = self.mode {
+                    [&self.listing, &self.other]
+                        .iter()
+                        .filter_map(|pane| pane.selected_entry())
+                        .filter(|(_, is_dir)| !is_dir)
+                        .map(|(path, _)| path)
+                        .collect()
+                } else {
+                    self.listing
+                        .selected_entries()
+                        .into_iter()
+                        .filter(|(_, is_dir)| !is_dir)
+                        .map(|(path, _)| path)
+                        .collect()
+                };
+                match files.as_slice() {
+                    [left, right] => return load_diff(left.clone(), right.clone()),
+                    [first] => {
+                        let first = first.clone();
+                        return Command::perform(pick_file(), move |result| {
+                            Message::DiffSecondPicked(first.clone(), result)
+                        });
+                    }
+                    _ => self.task_status = Some("Select two files to compare".to_string()),
+                }
+            }
+            Message::DiffSecondPicked(first, result) => match result {
+                Ok(second) => return load_diff(first, second),
+                Err(error) => self.error = Some(error),
+            },
+            Message::DiffFilesLoaded(result) => match result {
+                Ok(((left_path, left), (right_path, right))) => {
+                    return Command::perform(
+                        TextDiff::compute(
+                            DiffSide::new(Source::File(left_path), &left),
+                            DiffSide::new(Source::File(right_path), &right),
+                        ),
+                        Message::DiffComputed,
+                    );
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::CompareWithSaved => {
+                if let Some(path) = self.active_tab.map(|index| self.tabs[index].path.clone()) {
+                    return Command::perform(read_file(path), Message::SavedVersionLoaded);
+                }
+            }
+            // The buffer is read when the saved text arrives, so edits made meanwhile count
+            Message::SavedVersionLoaded(result) => match result {
+                Ok((path, saved)) => {
+                    let buffer = self.file_content.text();
+                    return Command::perform(
+                        TextDiff::compute(
+                            DiffSide::new(Source::File(path.clone()), &saved),
+                            DiffSide::new(Source::Buffer(path), &buffer),
+                        ),
+                        Message::DiffComputed,
+                    );
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::DiffComputed(result) => match result {
+                Ok(diff) => return self.show_diff(diff),
+                Err(error) => self.error = Some(error),
+            },
+            Message::DiffGoTo(index) => {
+                if let Some(diff) = &mut self.text_diff {
+                    if index < diff.hunks.len() {
+                        diff.current = index;
+                        return self.scroll_to_hunk();
+                    }
+                }
+            }
+            Message::DiffCopyHunk(from) => {
+                if let Some(diff) = &mut self.text_diff {
+                    diff.copy_hunk(from);
+                }
+            }
+            Message::DiffSave(side) => {
+                let Some(diff) = &mut self.text_diff else {
+                    return Command::none();
+                };
+                let text = diff.side(side).text();
+                match diff.side(side).source.clone() {
+                    Source::Buffer(path) => {
+                        diff.side_mut(side).dirty = false;
+                        self.set_buffer_text(&path, &text);
+                    }
+                    Source::Version(..) => {}
+                    Source::File(path) => {
+                        return Command::perform(
+                            save_file(Some(path), text, self.history_retention),
+                            move |result| Message::DiffSaved(side, result),
+                        );
+                    }
+                }
+            }
+            Message::DiffSaved(side, result) => match result {
+                Ok(_) => {
+                    if let Some(diff) = &mut self.text_diff {
+                        diff.side_mut(side).dirty = false;
+                    }
+                }
+                Err(error) => self.error = Some(error),
+            },
+            // Like Compare Folders, the commander syncs its left pane into the right one and
+            // the folder page asks for the destination
+            Message::SyncFolders => {
+                if let Mode::Commander = self.mode {
+                    if let (Some(left), Some(right)) =
+                        (self.listing.root.clone(), self.other.root.clone())
+                    {
+                        return self.start_sync(left, right);
+                    }
+                }
+                return Command::perform(pick_folder(), Message::SyncFolderPicked);
+            }
+            Message::SyncFolderPicked(result) => match (result, self.listing.root.clone()) {
+                (Ok(right), Some(left)) => return self.start_sync(left, right),
+                (Err(error), _) => self.error = Some(error),
+                _ => {}
+            },
+            Message::SyncModeSelected(mode) => {
+                if let Some(job) = &mut self.sync_job {
+                    job.mode = mode;
+                }
+                return self.preview_sync();
+            }
+            Message::SyncDeleteExtras(delete_extras) => {
+                if let Some(job) = &mut self.sync_job {
+                    job.delete_extras = delete_extras;
+                }
+                return self.preview_sync();
+            }
+            Message::SyncPreview => return self.preview_sync(),
+            Message::SyncPlanned(result) => {
+                if let Some(job) = &mut self.sync_job {
+                    job.running = false;
+                    match result {
+                        Ok(plan) => job.plan = plan,
+                        Err(error) => {
+                            job.plan.clear();
+                            self.error = Some(error);
+                        }
+                    }
+                }
+            }
+            Message::RunSync => {
+                if let Some(job) = &mut self.sync_job {
+                    job.running = true;
+                    return Command::perform(
+                        sync::apply(
+                            job.left.clone(),
+                            job.right.clone(),
+                            job.mode,
+                            std::mem::take(&mut job.plan),
+                        ),
+                        Message::Synced,
+                    );
+                }
+            }
+            Message::Synced(result) => {
+                self.index_root = None;
+                match result {
+                    Ok(count) => self.task_status = Some(format!("Synced {} entries", count)),
+                    Err(error) => {
+                        self.task_status = Some(format!("Sync failed: {}", error));
+                        self.error = Some(error);
+                    }
+                }
+                // The new dry run should be empty apart from conflicts, and the panes may
+                // show either folder
+                return Command::batch([self.preview_sync(), self.reload_panes()]);
+            }
+            Message::FilePicked(result) => match result {
+                Ok(path) => return open_entry(path, false),
+                Err(error) => self.error = Some(error),
+            },
+            Message::ExtractHere => {
+                if let Some(archive_path) = self.listing.selected_archive() {
+                    let mut destination = archive_path.clone();
+                    destination.pop();
+                    destination.push(archive::archive_stem(&archive_path));
+                    return self.extract(archive_path, destination);
+                }
+            }
+            Message::ExtractTo => {
+                return Command::perform(pick_folder(), Message::ExtractFolderPicked);
+            }
+            Message::ExtractFolderPicked(result) => match result {
+                Ok(destination) => {
+                    if let Some(archive_path) = self.listing.selected_archive() {
+                        return self.extract(archive_path, destination);
+                    }
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::Extracting(progress) => match progress {
+                archive::Progress::Entry { done, total } => {
+                    self.task_status = Some(format!("Extracting {}/{}", done, total));
+                }
+                archive::Progress::Finished(Ok(destination)) => {
+                    self.task_status = Some(format!("Extracted to {}", destination.display()));
+                    return self.reload_listing();
+                }
+                archive::Progress::Finished(Err(error)) => {
+                    self.task_status = Some(format!("Extract failed: {}", error));
+                    self.error = Some(error);
+                }
+            },
+            Message::Compress => {
+                let sources: Vec<PathBuf> = self
+                    .listing
+                    .selected_entries()
+                    .into_iter()
+                    .map(|(path, _)| path)
+                    .collect();
+                let stem = match sources.as_slice() {
+                    [single] => single.file_name(),
+                    _ => self.listing.root.as_deref().and_then(Path::file_name),
+                };
+                self.compress_name = stem
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "archive".to_string());
+                self.compress_sources = sources;
+                self.mode = Mode::Compress;
+            }
+            Message::CompressName(name) => self.compress_name = name,
+            Message::CompressFormat(format) => self.compress_format = format,
+            Message::CompressLevel(level) => {
+                self.compress_level = level;
+                self.settings_changed = true;
+            }
+            Message::CancelCompress => self.mode = Mode::OnDir,
+            Message::ConfirmCompress => {
+                self.mode = Mode::OnDir;
+                let Some(root) = self.listing.root.clone() else {
+                    return Command::none();
+                };
+                let destination = unique_path(
+                    &root,
+                    self.compress_name.trim(),
+                    self.compress_format.extension(),
+                );
+                let sources = std::mem::take(&mut self.compress_sources);
+                let format = self.compress_format;
+                let level = self.compress_level as u32;
+                self.task_status = Some(format!("Compressing into {}", destination.display()));
+                return iced::command::channel(100, move |progress| {
+                    archive::create_archive(sources, destination, format, level, progress)
+                })
+                .map(Message::Compressing);
+            }
+            Message::Compressing(progress) => match progress {
+                archive::Progress::Entry { done, total } => {
+                    self.task_status = Some(format!("Compressing {}/{}", done, total));
+                }
+                archive::Progress::Finished(Ok(destination)) => {
+                    self.task_status = Some(format!("Created {}", destination.display()));
+                    return self.reload_listing();
+                }
+                archive::Progress::Finished(Err(error)) => {
+                    self.task_status = Some(format!("Compress failed: {}", error));
+                    self.error = Some(error);
+                }
+            },
+            Message::BulkRename => {
+                let Some(folder) = self.listing.root.clone() else {
+                    return Command::none();
+                };
+                let paths = self
+                    .listing
+                    .selected_entries()
+                    .into_iter()
+                    .map(|(path, _)| path)
+                    .collect();
+                return Command::perform(rename::load(folder.clone(), paths), move |result| {
+                    Message::RenameLoaded(folder.clone(), result)
+                });
+            }
+            Message::RenameLoaded(folder, result) => match result {
+                Ok((sources, existing)) => {
+                    self.bulk_rename = Some(BulkRename::new(folder, sources, existing));
+                    self.mode = Mode::Rename;
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::RenameFind(find) => {
+                if let Some(rename) = &mut self.bulk_rename {
+                    rename.find = find;
+                }
+            }
+            Message::RenameReplace(replace) => {
+                if let Some(rename) = &mut self.bulk_rename {
+                    rename.replace = replace;
+                }
+            }
+            Message::RenameRegex(regex) => {
+                if let Some(rename) = &mut self.bulk_rename {
+                    rename.regex = regex;
+                }
+            }
+            Message::RenamePattern(pattern) => {
+                if let Some(rename) = &mut self.bulk_rename {
+                    rename.pattern = pattern;
+                }
+            }
+            Message::RenameStart(start) => {
+                if let Some(rename) = &mut self.bulk_rename {
+                    rename.start = start;
+                }
+            }
+            Message::RenameCase(case) => {
+                if let Some(rename) = &mut self.bulk_rename {
+                    rename.case = case;
+                }
+            }
+            Message::CancelRename => {
+                self.bulk_rename = None;
+                self.mode = Mode::OnDir;
+            }
+            Message::ConfirmRename => {
+                self.mode = Mode::OnDir;
+                let Some(rename) = self.bulk_rename.take() else {
+                    return Command::none();
+                };
+                // The button is only enabled without problems, this is the same check
+                let Ok(rows) = rename.preview() else {
+                    return Command::none();
+                };
+                if rows.iter().any(|row| row.problem.is_some()) {
+                    return Command::none();
+                }
+                let renames = rows
+                    .into_iter()
+                    .filter(|row| row.changed())
+                    .map(|row| (row.old, row.new))
+                    .collect();
+                return Command::perform(rename::apply(rename.folder, renames), Message::Renamed);
+            }
+            Message::Renamed(result) => {
+                match result {
+                    Ok(count) => self.task_status = Some(format!("Renamed {} entries", count)),
+                    Err(error) => {
+                        self.task_status = Some(format!("Rename failed: {}", error));
+                        self.error = Some(error);
+                    }
+                }
+                return self.reload_listing();
+            }
+            Message::FindDuplicates => {
+                let Some(root) = self.dir.clone() else {
+                    return Command::none();
+                };
+                if !matches!(self.mode, Mode::Duplicates) {
+                    self.previous_mode = self.mode.clone();
+                    self.mode = Mode::Duplicates;
+                }
+                self.duplicates = Some(Duplicates {
+                    root: root.clone(),
+                    running: true,
+                    groups: Vec::new(),
+                });
+                return Command::perform(
+                    duplicates::find_duplicates(root),
+                    Message::DuplicatesFound,
+                );
+            }
+            Message::DuplicatesFound(result) => {
+                if let Some(found) = &mut self.duplicates {
+                    found.running = false;
+                    match result {
+                        Ok(groups) => found.groups = groups,
+                        Err(error) => self.error = Some(error),
+                    }
+                }
+            }
+            Message::KeepDuplicate(group, index) => {
+                if let Some(group) = self
+                    .duplicates
+                    .as_mut()
+                    .and_then(|found| found.groups.get_mut(group))
+                {
+                    group.keep = index;
+                }
+            }
+            Message::TrashDuplicates(group) => {
+                if let Some(group) = self.duplicate_group(group) {
+                    return Command::perform(
+                        duplicates::trash(
+                            group.paths[group.keep].clone(),
+                            group.size,
+                            group.others(),
+                        ),
+                        Message::DuplicatesResolved,
+                    );
+                }
+            }
+            Message::LinkDuplicates(group) => {
+                if let Some(group) = self.duplicate_group(group) {
+                    return Command::perform(
+                        duplicates::hard_link(
+                            group.paths[group.keep].clone(),
+                            group.size,
+                            group.others(),
+                        ),
+                        Message::DuplicatesResolved,
+                    );
+                }
+            }
+            Message::DuplicatesResolved(result) => match result {
+                Ok((paths, skipped)) => {
+                    self.index_root = None;
+                    if let Some(found) = &mut self.duplicates {
+                        found.resolved(&paths);
+                    }
+                    self.task_status = Some(if skipped == 0 {
+                        format!("Resolved {} duplicates", paths.len())
+                    } else {
+                        format!(
+                            "Resolved {} duplicates, {} changed since the search and were left alone",
+                            paths.len(),
+                            skipped
+                        )
+                    });
+                }
+                // Some copies may be gone already, so the folder is searched again
+                Err(error) => {
+                    self.error = Some(error);
+                    return self.handle_message(Message::FindDuplicates);
+                }
+            },
+            // Refreshing keeps the folder drilled into and the sort order
+            Message::DiskUsage => {
+                let Some(root) = self.dir.clone() else {
+                    return Command::none();
+                };
+                if let Mode::DiskUsage = self.mode {
+                    if let Some(usage) = &mut self.disk_usage {
+                        usage.running = true;
+                        return Command::perform(
+                            disk_usage::scan(usage.root.clone()),
+                            Message::DiskUsageScanned,
+                        );
+                    }
+                }
+                self.previous_mode = self.mode.clone();
+                self.mode = Mode::DiskUsage;
+                let sort = self
+                    .disk_usage
+                    .as_ref()
+                    .map_or(UsageSort::Size, |old| old.sort);
+                self.disk_usage = Some(DiskUsage {
+                    root: root.clone(),
+                    running: true,
+                    tree: None,
+                    open: Vec::new(),
+                    sort,
+                });
+                return Command::perform(disk_usage::scan(root), Message::DiskUsageScanned);
+            }
+            Message::DiskUsageScanned(result) => {
+                if let Some(usage) = &mut self.disk_usage {
+                    usage.running = false;
+                    match result {
+                        Ok(tree) => {
+                            usage.tree = Some(tree);
+                            // A folder that went away since the last scan
+                            if usage.current().is_none() {
+                                usage.open.clear();
+                            }
+                        }
+                        Err(error) => self.error = Some(error),
+                    }
+                }
+            }
+            Message::UsageOpen(name) => {
+                if let Some(usage) = &mut self.disk_usage {
+                    usage.open.push(name);
+                }
+            }
+            Message::UsageUp => {
+                if let Some(usage) = &mut self.disk_usage {
+                    usage.open.pop();
+                }
+            }
+            Message::UsageSortBy(sort) => {
+                if let Some(usage) = &mut self.disk_usage {
+                    usage.sort = sort;
+                }
+            }
+            Message::UsageShowFolder => {
+                if let Some(usage) = &self.disk_usage {
+                    return open_entry(usage.current_path(), true);
+                }
+            }
+            Message::Checksums => {
+                let sources: Vec<PathBuf> = self
+                    .listing
+                    .selected_entries()
+                    .into_iter()
+                    .filter(|(_, is_dir)| !is_dir)
+                    .map(|(path, _)| path)
+                    .collect();
+                if sources.is_empty() {
+                    self.task_status = Some("Select files to calculate checksums".to_string());
+                    return Command::none();
+                }
+                self.previous_mode = self.mode.clone();
+                self.mode = Mode::Checksums;
+                self.checksums = Some(Checksums::Hashes {
+                    sources,
+                    running: false,
+                    hashes: Vec::new(),
+                });
+                return self.rehash();
+            }
+            Message::ChecksumAlgorithm(algorithm) => {
+                self.checksum_algorithm = algorithm;
+                return self.rehash();
+            }
+            Message::ChecksumsHashed(result) => {
+                if let Some(Checksums::Hashes {
+                    running, hashes, ..
+                }) = &mut self.checksums
+                {
+                    *running = false;
+                    match result {
+                        Ok(hashed) => *hashes = hashed,
+                        Err(error) => self.error = Some(error),
+                    }
+                }
+            }
+            Message::CopyChecksum(hash) => return iced::clipboard::write(hash),
+            // Only buffers edited since their last swap file are written again
+            Message::WriteSwaps => {
+                let buffers: Vec<(usize, PathBuf, u64)> = self
+                    .tabs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, tab)| {
+                        tab.unsaved() && tab.swapped != tab.revision && !self.swap_pending(&tab.path)
+                    })
+                    .map(|(index, tab)| (index, tab.path.clone(), tab.revision))
+                    .collect();
+                if buffers.is_empty() {
+                    return Command::none();
+                }
+                let texts = buffers
+                    .iter()
+                    .map(|(index, path, _)| (path.clone(), self.buffer_text(*index)))
+                    .collect();
+                let revisions = buffers
+                    .into_iter()
+                    .map(|(_, path, revision)| (path, revision))
+                    .collect();
+                return Command::perform(recovery::write(texts), move |result| {
+                    Message::SwapsWritten(revisions, result)
+                });
+            }
+            Message::SwapsWritten(revisions, result) => match result {
+                Ok(()) => {
+                    for (path, revision) in revisions {
+                        if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.path == path) {
+                            tab.swapped = revision;
+                        }
+                    }
+                }
+                Err(error) => eprintln!("rust-vfs: writing swap files failed: {}", error),
+            },
+            Message::SwapsRemoved => {}
+            // The swap's text goes into a tab as an unsaved edit, the file on disk is untouched
+            Message::RecoverSwap(index) => {
+                if index < self.swaps.len() {
+                    let swap = self.swaps.remove(index);
+                    self.open_tab(swap.path.clone(), &swap.text);
+                    if let Some(tab) = self.active_tab.map(|active| &mut self.tabs[active]) {
+                        tab.revision += 1;
+                    }
+                    if self.swaps.is_empty() {
+                        return self.handle_message(Message::RecoveryDone);
+                    }
+                }
+            }
+            Message::CompareSwap(index) => {
+                if let Some(path) = self.swaps.get(index).map(|swap| swap.path.clone()) {
+                    return Command::perform(read_file(path.clone()), move |result| {
+                        Message::SwapFileLoaded(path, result)
+                    });
+                }
+            }
+            Message::SwapFileLoaded(path, result) => match result {
+                Ok((_, saved)) => {
+                    let Some(swap) = self.swaps.iter().find(|swap| swap.path == path) else {
+                        return Command::none();
+                    };
+                    let unsaved = format!("unsaved edits from {}", swap.label());
+                    let diff = TextDiff::compute(
+                        DiffSide::new(Source::File(path.clone()), &saved),
+                        DiffSide::new(Source::Version(path, unsaved), &swap.text),
+                    );
+                    return Command::perform(diff, Message::DiffComputed);
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::DiscardSwap(index) => {
+                if index < self.swaps.len() {
+                    let swap = self.swaps.remove(index);
+                    let removed =
+                        Command::perform(recovery::remove(vec![swap.path]), |_| Message::SwapsRemoved);
+                    if self.swaps.is_empty() {
+                        return Command::batch([removed, self.handle_message(Message::RecoveryDone)]);
+                    }
+                    return removed;
+                }
+            }
+            // Swaps left undecided stay on disk and in swaps, and are offered again next time
+            Message::RecoveryDone => {
+                let Some(startup) = self.startup.take() else {
+                    return Command::none();
+                };
+                self.mode = Mode::Start;
+                let recovered = match self.active_tab {
+                    Some(index) => self.switch_tab(index),
+                    None => Command::none(),
+                };
+                return Command::batch([recovered, startup.command()]);
+            }
+            Message::FocusLost => {
+                if self.auto_save_on_focus_loss {
+                    return self.handle_message(Message::AutoSave);
+                }
+            }
+            // Every unsaved tab is saved in place. Files inside archives can't be written
+            Message::AutoSave => {
+                let saves: Vec<Command<Message>> = (0..self.tabs.len())
+                    .filter(|index| {
+                        let tab = &self.tabs[*index];
+                        tab.unsaved() && archive::split_archive_path(&tab.path).is_none()
+                    })
+                    .map(|index| {
+                        let path = self.tabs[index].path.clone();
+                        let revision = self.tabs[index].revision;
+                        let text = self.buffer_text(index);
+                        Command::perform(
+                            save_file(Some(path.clone()), text, self.history_retention),
+                            move |result| Message::AutoSaved(path, revision, result),
+                        )
+                    })
+                    .collect();
+                return Command::batch(saves);
+            }
+            Message::AutoSaved(path, revision, result) => match result {
+                // Edits made while saving keep their swap file
+                Ok(_) => {
+                    if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.path == path) {
+                        tab.saved = revision;
+                        if !tab.unsaved() {
+                            return self.remove_swaps(vec![path]);
+                        }
+                    }
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::ToggleAutoSave => {
+                self.auto_save_on_focus_loss = !self.auto_save_on_focus_loss;
+                self.settings_changed = true;
+                self.task_status = Some(
+                    if self.auto_save_on_focus_loss {
+                        "Unsaved tabs are saved when the window loses focus"
+                    } else {
+                        "Tabs are no longer saved when the window loses focus"
+                    }
+                    .to_string(),
+                );
+            }
+            Message::Snapshots | Message::TakeSnapshot => {
+                let take = matches!(message, Message::TakeSnapshot);
+                let root = match &self.snapshots {
+                    Some(snapshots) if matches!(self.mode, Mode::Snapshots) => {
+                        snapshots.root.clone()
+                    }
+                    _ => {
+                        let Some(root) = self.dir.clone() else {
+                            return Command::none();
+                        };
+                        self.previous_mode = self.mode.clone();
+                        self.mode = Mode::Snapshots;
+                        self.snapshots = Some(Snapshots {
+                            root: root.clone(),
+                            running: false,
+                            list: Vec::new(),
+                            compared: None,
+                            changes: Vec::new(),
+                        });
+                        root
+                    }
+                };
+                if let Some(snapshots) = &mut self.snapshots {
+                    snapshots.running = true;
+                }
+                if !take {
+                    return Command::perform(snapshot::list(root.clone()), move |result| {
+                        Message::SnapshotsListed(root, result)
+                    });
+                }
+                self.task_status = Some(format!("Taking a snapshot of {}...", root.display()));
+                return Command::perform(snapshot::take(root.clone()), move |result| {
+                    Message::SnapshotTaken(root, result)
+                });
+            }
+            Message::SnapshotsListed(root, result) => {
+                if let Some(snapshots) =
+                    self.snapshots.as_mut().filter(|snapshots| snapshots.root == root)
+                {
+                    snapshots.running = false;
+                    match result {
+                        Ok(list) => {
+                            snapshots.list = list;
+                            snapshots.compared = None;
+                            snapshots.changes.clear();
+                        }
+                        Err(error) => self.error = Some(error),
+                    }
+                }
+            }
+            Message::SnapshotTaken(root, result) => match result {
+                Ok(info) => {
+                    self.task_status = Some(format!(
+                        "Snapshot of {} files ({}) taken",
+                        info.files,
+                        format_size(info.size)
+                    ));
+                    return Command::perform(snapshot::list(root.clone()), move |result| {
+                        Message::SnapshotsListed(root, result)
+                    });
+                }
+                Err(error) => {
+                    if let Some(snapshots) = &mut self.snapshots {
+                        snapshots.running = false;
+                    }
+                    self.task_status = None;
+                    self.error = Some(error);
+                }
+            },
+            Message::CompareSnapshot(index) => {
+                if let Some(snapshots) = &mut self.snapshots {
+                    snapshots.compared = Some(index);
+                    if let Some(manifest) = snapshots.compared_manifest() {
+                        snapshots.running = true;
+                        return Command::perform(
+                            snapshot::diff(manifest.clone(), snapshots.root.clone()),
+                            move |result| Message::SnapshotCompared(manifest, result),
+                        );
+                    }
+                }
+            }
+            Message::SnapshotCompared(manifest, result) => {
+                if let Some(snapshots) = self
+                    .snapshots
+                    .as_mut()
+                    .filter(|snapshots| snapshots.compared_manifest() == Some(manifest))
+                {
+                    snapshots.running = false;
+                    match result {
+                        Ok(changes) => snapshots.changes = changes,
+                        Err(error) => self.error = Some(error),
+                    }
+                }
+            }
+            Message::RestoreChange(_) | Message::RestoreSnapshot => {
+                let Some(snapshots) = &mut self.snapshots else {
+                    return Command::none();
+                };
+                let changes = match message {
+                    Message::RestoreChange(index) => {
+                        snapshots.changes.get(index).cloned().into_iter().collect()
+                    }
+                    _ => snapshots.changes.clone(),
+                };
+                let Some(manifest) = snapshots.compared_manifest() else {
+                    return Command::none();
+                };
+                snapshots.running = true;
+                return Command::perform(
+                    snapshot::restore(manifest, snapshots.root.clone(), changes),
+                    Message::SnapshotRestored,
+                );
+            }
+            // Compared again, so whatever couldn't be restored still shows
+            Message::SnapshotRestored(result) => {
+                self.index_root = None;
+                match result {
+                    Ok(count) => self.task_status = Some(format!("Restored {} entries", count)),
+                    Err(error) => self.error = Some(error),
+                }
+                if let Some(index) = self.snapshots.as_ref().and_then(|snapshots| snapshots.compared) {
+                    return self.handle_message(Message::CompareSnapshot(index));
+                }
+            }
+            Message::DeleteSnapshot(index) => {
+                if let Some(snapshots) = &mut self.snapshots {
+                    if let Some(info) = snapshots.list.get(index) {
+                        snapshots.running = true;
+                        return Command::perform(
+                            snapshot::delete(info.manifest.clone()),
+                            Message::SnapshotDeleted,
+                        );
+                    }
+                }
+            }
+            Message::SnapshotDeleted(result) => {
+                if let Err(error) = result {
+                    self.error = Some(error);
+                }
+                if let Some(root) = self.snapshots.as_ref().map(|snapshots| snapshots.root.clone()) {
+                    return Command::perform(snapshot::list(root.clone()), move |result| {
+                        Message::SnapshotsListed(root, result)
+                    });
+                }
+            }
+            Message::FileHistory => {
+                if let Some(file) = self.active_tab.map(|index| self.tabs[index].path.clone()) {
+                    self.mode = Mode::History;
+                    self.history = Some(History {
+                        file: file.clone(),
+                        loading: true,
+                        versions: Vec::new(),
+                    });
+                    return Command::perform(history::versions(file.clone()), move |result| {
+                        Message::HistoryLoaded(file, result)
+                    });
+                }
+            }
+            Message::HistoryLoaded(file, result) => {
+                if let Some(history) = self.history.as_mut().filter(|history| history.file == file) {
+                    history.loading = false;
+                    match result {
+                        Ok(versions) => history.versions = versions,
+                        Err(error) => self.error = Some(error),
+                    }
+                }
+            }
+            Message::HistoryCompare(index) | Message::HistoryRestore(index) => {
+                let restore = matches!(message, Message::HistoryRestore(_));
+                let Some(version) = self
+                    .history
+                    .as_ref()
+                    .and_then(|history| history.versions.get(index).cloned())
+                else {
+                    return Command::none();
+                };
+                return Command::perform(
+                    history::read_version(version.stored.clone()),
+                    move |result| Message::VersionLoaded(version, restore, result),
+                );
+            }
+            // A restored version replaces the buffer, the file itself only changes on the next
+            // save, which keeps what it replaces in the history too
+            Message::VersionLoaded(version, restore, result) => {
+                let Some(file) = self.history.as_ref().map(|history| history.file.clone()) else {
+                    return Command::none();
+                };
+                match result {
+                    Ok(kept) if restore => {
+                        self.set_buffer_text(&file, &kept);
+                        self.mode = Mode::Opened;
+                    }
+                    Ok(kept) => {
+                        let buffer = self.file_content.text();
+                        return Command::perform(
+                            TextDiff::compute(
+                                DiffSide::new(
+                                    Source::Version(file.clone(), format!("saved {}", version.label())),
+                                    &kept,
+                                ),
+                                DiffSide::new(Source::Buffer(file), &buffer),
+                            ),
+                            Message::DiffComputed,
+                        );
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::WriteManifest => {
+                if let Some(folder) = self.dir.clone() {
+                    self.task_status = Some(format!(
+                        "Writing {}",
+                        self.checksum_algorithm.manifest_name()
+                    ));
+                    return Command::perform(
+                        checksum::write_manifest(folder, self.checksum_algorithm),
+                        Message::ManifestWritten,
+                    );
+                }
+            }
+            Message::ManifestWritten(result) => match result {
+                Ok((manifest, count)) => {
+                    self.task_status =
+                        Some(format!("Wrote {} for {} files", manifest.display(), count));
+                    return self.reload_listing();
+                }
+                Err(error) => {
+                    self.task_status = Some(format!("Writing the manifest failed: {}", error));
+                    self.error = Some(error);
+                }
+            },
+            // A selected file is taken as the manifest, otherwise one is asked for
+            Message::VerifyManifest => match self.listing.selected_entries().as_slice() {
+                [(manifest, false)] => {
+                    return self.handle_message(Message::ManifestPicked(Ok(manifest.clone())))
+                }
+                _ => return Command::perform(pick_file(), Message::ManifestPicked),
+            },
+            Message::ManifestPicked(result) => match result {
+                Ok(manifest) => {
+                    if !matches!(self.mode, Mode::Checksums) {
+                        self.previous_mode = self.mode.clone();
+                        self.mode = Mode::Checksums;
+                    }
+                    self.checksums = Some(Checksums::Verify {
+                        manifest: manifest.clone(),
+                        running: true,
+                        results: Vec::new(),
+                    });
+                    return Command::perform(
+                        checksum::verify_manifest(manifest),
+                        Message::ManifestVerified,
+                    );
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::ManifestVerified(result) => {
+                if let Some(Checksums::Verify {
+                    running, results, ..
+                }) = &mut self.checksums
+                {
+                    *running = false;
+                    match result {
+                        Ok(verified) => *results = verified,
+                        Err(error) => self.error = Some(error),
+                    }
+                }
+            }
+            Message::SwitchTab(index) => return self.switch_tab(index),
+            Message::FilesLoaded(results, active, folder) => {
+                let mut opened = 0;
+                for result in results {
+                    match result {
+                        Ok((path, content)) => {
+                            self.open_tab(path, &content);
+                            opened += 1;
+                        }
+                        Err(error) => self.error = Some(error),
+                    }
+                }
+                if opened > 0 {
+                    let index = active.filter(|index| *index < self.tabs.len());
+                    let switched = self.switch_tab(index.unwrap_or(self.tabs.len() - 1));
+                    // The listing shows the folder that was asked for, not the file's own
+                    return match folder {
+                        Some(folder) => {
+                            Command::perform(read_directory(folder), Message::LoadFileFolder)
+                        }
+                        None => switched,
+                    };
+                }
+                if let Some(folder) = folder {
+                    return Command::perform(read_directory(folder), Message::LoadFolder);
+                }
+            }
+            Message::SettingsSaved(result) => {
+                if let Err(error) = result {
+                    eprintln!("rust-vfs: saving settings failed: {}", error);
+                }
+            }
+            Message::WindowResized(width, height) => {
+                self.window_size = (width as f32, height as f32);
+            }
+            // Unsaved edits are never dropped on exit. Their latest text goes to the swap files
+            // so the next start offers to recover them, swaps of tabs saved since are removed
+            Message::CloseRequested => {
+                let unsaved: Vec<(PathBuf, String)> = self
+                    .tabs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, tab)| tab.unsaved() && !self.swap_pending(&tab.path))
+                    .map(|(index, tab)| (tab.path.clone(), self.buffer_text(index)))
+                    .collect();
+                let saved: Vec<PathBuf> = self
+                    .tabs
+                    .iter()
+                    .filter(|tab| tab.swapped > 0 && !tab.unsaved() && !self.swap_pending(&tab.path))
+                    .map(|tab| tab.path.clone())
+                    .collect();
+                let save = settings::save(self.current_settings());
+                return Command::perform(
+                    async move {
+                        if let Err(error) = recovery::write(unsaved).await {
+                            eprintln!("rust-vfs: writing swap files failed: {}", error);
+                        }
+                        recovery::remove(saved).await;
+                        save.await
+                    },
+                    |_| Message::Exit,
+                );
+            }
+            Message::Exit => return iced::window::close(iced::window::Id::MAIN),
+            // Closing an unsaved tab keeps its edits in a swap file, the next start offers them
+            Message::CloseTab(index) => {
+                let Some(tab) = self.tabs.get(index) else {
+                    return Command::none();
+                };
+                let was_active = self.active_tab == Some(index);
+                let path = tab.path.clone();
+                let kept =
+                    (tab.unsaved() && !self.swap_pending(&path)).then(|| self.buffer_text(index));
+                self.close_tab(index);
+                let swap = match kept {
+                    Some(text) => {
+                        self.task_status = Some(format!(
+                            "Unsaved edits to {} are kept and offered at the next start",
+                            path.display()
+                        ));
+                        self.swaps.push(recovery::Swap {
+                            path: path.clone(),
+                            text: text.clone(),
+                            written: std::time::SystemTime::now(),
+                        });
+                        Command::perform(recovery::write(vec![(path, text)]), |result| {
+                            Message::SwapsWritten(Vec::new(), result)
+                        })
+                    }
+                    None => self.remove_swaps(vec![path]),
+                };
+                if was_active {
+                    match self.tabs.len() {
+                        0 => self.mode = Mode::OnDir,
+                        len => return Command::batch([swap, self.switch_tab(index.min(len - 1))]),
+                    }
+                }
+                return swap;
+            }
+            Message::OpenFile => return Command::perform(pick_file(), Message::FilePicked),
+            Message::OpenFolder => return Command::perform(open_folder(), Message::LoadFolder),
+        }
+        Command::none()
+    }
+
+    // Both panes are filtered the same way
+    fn render_listing(&mut self) {
+        self.listing.render(&self.filter);
+        self.other.render(&self.filter);
+    }
+
+    fn pane(&self, side: Side) -> &Listing {
+        match side {
+            Side::Left => &self.listing,
+            Side::Right => &self.other,
+        }
+    }
+
+    // Reloads the folder after a file operation. The quick-open index may be out of date now
+    // too, so it is built again the next time quick open is used
+    fn reload_listing(&mut self) -> Command<Message> {
+        self.index_root = None;
+        match self.listing.root.clone() {
+            Some(root) => Command::perform(read_directory(root), Message::LoadFileFolder),
+            None => Command::none(),
+        }
+    }
+
+    fn reload_panes(&mut self) -> Command<Message> {
+        self.index_root = None;
+        Command::batch([Side::Left, Side::Right].into_iter().filter_map(|side| {
+            let root = self.pane(side).root.clone()?;
+            Some(Command::perform(read_directory(root), move |result| {
+                Message::PaneLoaded(side, result)
+            }))
+        }))
+    }
+
+    // F5 and F6, the selection of the focused pane goes into the other pane's folder.
+    // Entries inside an archive can only be copied out, never moved
+    fn transfer_to_other(&mut self, moving: bool) -> Command<Message> {
+        let sources: Vec<PathBuf> = self
+            .pane(self.active_side)
+            .selected_entries()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        let Some(destination) = self.pane(self.active_side.other()).root.clone() else {
+            return Command::none();
+        };
+        if sources.is_empty() {
+            return Command::none();
+        }
+        if archive::split_archive_path(&destination).is_some() {
+            self.task_status = Some("Archives are read-only".to_string());
+            return Command::none();
+        }
+        // Entries already in the destination would only be copied or moved onto themselves
+        let in_place = |source: &PathBuf| {
+            links::same_file(source, &destination)
+                || source
+                    .parent()
+                    .is_some_and(|folder| links::same_file(folder, &destination))
+        };
+        if sources.iter().any(in_place) {
+            self.task_status = Some("Both panes show the same folder".to_string());
+            return Command::none();
+        }
+        let verb = if moving { "Moving" } else { "Copying" };
+        self.task_status = Some(format!(
+            "{} {} entries to {}",
+            verb,
+            sources.len(),
+            destination.display()
+        ));
+        let verify = self.verify_copies;
+        let links = self.link_mode();
+        Command::perform(
+            async move {
+                for source in &sources {
+                    if moving && archive::split_archive_path(source).is_none() {
+                        move_into(source, &destination, verify, links).await?;
+                    } else {
+                        paste_verified(source, &destination, verify, links).await?;
+                    }
+                }
+                Ok(sources.len())
+            },
+            Message::PaneTransferred,
+        )
+    }
+
+    fn pane_view(&self, side: Side) -> iced::Element<'_, Message> {
+        let listing = self.pane(side);
+        let marker = if self.active_side == side { "▶ " } else { "" };
+        let path = listing
+            .root
+            .as_deref()
+            .map(|root| root.display().to_string())
+            .unwrap_or_default();
+        let header = row![
+            text(format!("{}{}", marker, path)),
+            horizontal_space(),
+            button("Up").on_press_maybe(listing.root.is_some().then_some(Message::PaneUp(side)))
+        ];
+        let entries = text_editor(&listing.content)
+            .on_action(move |action| Message::ListingAction(side, action))
+            .style(self.editor_style())
+            .height(iced::Length::Fill);
+        column![header, entries].spacing(5).width(iced::Length::Fill).into()
+    }
+
+    fn start_compare(&mut self, left: PathBuf, right: PathBuf) -> Command<Message> {
+        // Archive contents can't be walked with the file system calls the compare uses
+        if archive::split_archive_path(&left).is_some()
+            || archive::split_archive_path(&right).is_some()
+        {
+            self.task_status = Some("Archives can't be compared".to_string());
+            return Command::none();
+        }
+        let contents = self.comparison.as_ref().is_some_and(|old| old.contents);
+        self.comparison = Some(Comparison {
+            left,
+            right,
+            contents,
+            running: false,
+            entries: Vec::new(),
+        });
+        self.previous_mode = self.mode.clone();
+        self.mode = Mode::Compare;
+        self.recompare()
+    }
+
+    fn start_sync(&mut self, left: PathBuf, right: PathBuf) -> Command<Message> {
+        if archive::split_archive_path(&left).is_some()
+            || archive::split_archive_path(&right).is_some()
+        {
+            self.task_status = Some("Archives can't be synced".to_string());
+            return Command::none();
+        }
+        if left == right {
+            self.task_status = Some("Pick two different folders to sync".to_string());
+            return Command::none();
+        }
+        // The mode and delete setting carry over from the last sync
+        let (mode, delete_extras) = self
+            .sync_job
+            .as_ref()
+            .map_or((SyncMode::Mirror, false), |old| (old.mode, old.delete_extras));
+        self.sync_job = Some(SyncJob {
+            left,
+            right,
+            mode,
+            delete_extras,
+            running: false,
+            plan: Vec::new(),
+        });
+        self.previous_mode = self.mode.clone();
+        self.mode = Mode::Sync;
+        self.preview_sync()
+    }
+
+    fn preview_sync(&mut self) -> Command<Message> {
+        let Some(job) = &mut self.sync_job else {
+            return Command::none();
+        };
+        job.running = true;
+        Command::perform(
+            sync::plan(job.left.clone(), job.right.clone(), job.mode, job.delete_extras),
+            Message::SyncPlanned,
+        )
+    }
+
+    fn sync_view(&self) -> iced::Element<'_, Message> {
+        let Some(job) = &self.sync_job else {
+            return text("Nothing to sync").into();
+        };
+        let idle = !job.running;
+        let runnable = job
+            .plan
+            .iter()
+            .any(|op| op.action != SyncAction::Conflict);
+        let mut header = row![
+            text("Sync Folders").size(30),
+            horizontal_space(),
+            PickList::new(SyncMode::ALL, Some(job.mode), Message::SyncModeSelected),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+        if job.mode == SyncMode::Mirror {
+            let mut delete = checkbox("Delete extras", job.delete_extras);
+            if idle {
+                delete = delete.on_toggle(Message::SyncDeleteExtras);
+            }
+            header = header.push(delete);
+        }
+        let header = header
+            .push(button("Preview").on_press_maybe(idle.then_some(Message::SyncPreview)))
+            .push(button("Sync").on_press_maybe((idle && runnable).then_some(Message::RunSync)))
+            .push(button("Close").on_press(Message::CloseOverlay));
+        let status = if job.running {
+            "Working...".to_string()
+        } else if job.plan.is_empty() {
+            "Already in sync".to_string()
+        } else {
+            let count = |matches: fn(&SyncAction) -> bool| {
+                job.plan.iter().filter(|op| matches(&op.action)).count()
+            };
+            format!(
+                "Dry run: {} to copy, {} to delete, {} conflicts",
+                count(|action| matches!(action, SyncAction::Copy(_))),
+                count(|action| matches!(action, SyncAction::Delete(_))),
+                count(|action| matches!(action, SyncAction::Conflict))
+            )
+        };
+        let mut plan = column![].spacing(2);
+        for op in &job.plan {
+            let icon = if op.is_dir { "📁" } else { "📝" };
+            plan = plan.push(
+                row![
+                    text(op.action.to_string()).width(120),
+                    text(format!("{}{}", icon, op.relative)).width(iced::Length::Fill),
+                ]
+                .spacing(10),
+            );
+        }
+        container(
+            column![
+                header,
+                text(format!("{}  →  {}", job.left.display(), job.right.display())),
+                text(status),
+                scrollable(plan)
+            ]
+            .spacing(10),
+        )
+        .padding(50)
+        .into()
+    }
+
+    fn recompare(&mut self) -> Command<Message> {
+        let Some(comparison) = &mut self.comparison else {
+            return Command::none();
+        };
+        comparison.running = true;
+        Command::perform(
+            compare::compare_folders(
+                comparison.left.clone(),
+                comparison.right.clone(),
+                comparison.contents,
+            ),
+            Message::FoldersCompared,
+        )
+    }
+
+    fn compare_view(&self) -> iced::Element<'_, Message> {
+        let Some(comparison) = &self.comparison else {
+            return text("Nothing to compare").into();
+        };
+        let header = row![
+            text("Compare Folders").size(30),
+            horizontal_space(),
+            checkbox("Compare contents", comparison.contents).on_toggle(Message::CompareContents),
+            button("Refresh").on_press_maybe((!comparison.running).then_some(Message::Recompare)),
+            button("Close").on_press(Message::CloseOverlay),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+        let status = if comparison.running {
+            "Comparing...".to_string()
+        } else if comparison.entries.is_empty() {
+            "No differences".to_string()
+        } else {
+            let count = |kind| {
+                comparison
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.kind == kind && !(entry.is_dir && kind == DiffKind::Changed))
+                    .count()
+            };
+            format!(
+                "{} only left, {} only right, {} changed",
+                count(DiffKind::OnlyLeft),
+                count(DiffKind::OnlyRight),
+                count(DiffKind::Changed)
+            )
+        };
+        let mut tree = column![].spacing(2);
+        for entry in &comparison.entries {
+            let icon = if entry.is_dir { "📁" } else { "📝" };
+            let label = match entry.kind {
+                DiffKind::OnlyLeft => "only left",
+                DiffKind::OnlyRight => "only right",
+                DiffKind::Changed if entry.is_dir => "",
+                DiffKind::Changed => "changed",
+            };
+            let copy = |side: Side| {
+                entry
+                    .exists_on(side)
+                    .then(|| Message::CopyAcross(side, entry.relative.clone()))
+            };
+            tree = tree.push(
+                row![
+                    text(format!("{}{}{}", "    ".repeat(entry.depth), icon, entry.name))
+                        .width(iced::Length::Fill),
+                    text(label).width(120),
+                    button("Copy →").on_press_maybe(copy(Side::Left)),
+                    button("← Copy").on_press_maybe(copy(Side::Right)),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center),
+            );
+        }
+        container(column![
+            header,
+            text(format!(
+                "{}  ⇄  {}",
+                comparison.left.display(),
+                comparison.right.display()
+            )),
+            text(status),
+            scrollable(tree)
+        ]
+        .spacing(10))
+        .padding(50)
+        .into()
+    }
+
+    fn show_diff(&mut self, diff: TextDiff) -> Command<Message> {
+        self.text_diff = Some(diff);
+        self.previous_mode = self.mode.clone();
+        self.mode = Mode::Diff;
+        self.scroll_to_hunk()
+    }
+
+    // Rows all have the same height, so the offset of a hunk follows from its first row
+    fn scroll_to_hunk(&self) -> Command<Message> {
+        let Some(diff) = &self.text_diff else {
+            return Command::none();
+        };
+        let row = diff.hunks.get(diff.current).map_or(0, |hunk| hunk.first_row);
+        // A few rows of context stay visible above the hunk
+        let y = row.saturating_sub(3) as f32 * DIFF_ROW_HEIGHT;
+        scrollable::scroll_to(diff_scroll_id(), scrollable::AbsoluteOffset { x: 0.0, y })
+    }
+
+    // Text copied into the buffer side of a diff goes back to the tab it came from
+    fn set_buffer_text(&mut self, path: &Path, text: &str) {
+        let Some(index) = self.tabs.iter().position(|tab| tab.path == path) else {
+            return;
+        };
+        if self.active_tab == Some(index) {
+            self.file_content = text_editor::Content::with_text(text);
+        } else {
+            self.tabs[index].content = text_editor::Content::with_text(text);
+        }
+        self.tabs[index].revision += 1;
+    }
+
+    fn diff_view(&self) -> iced::Element<'_, Message> {
+        let Some(diff) = &self.text_diff else {
+            return text("Nothing to compare").into();
+        };
+        let palette = self.theme.palette();
+        let position = if diff.too_different {
+            "The files differ too much to compare".to_string()
+        } else if diff.hunks.is_empty() {
+            "No differences".to_string()
+        } else {
+            format!("Difference {} of {}", diff.current + 1, diff.hunks.len())
+        };
+        let has_hunk = !diff.hunks.is_empty();
+        let header = row![
+            text("Compare Files").size(30),
+            horizontal_space(),
+            text(position),
+            button(text(self.with_keys("Previous", Action::PreviousHunk)))
+                .on_press_maybe(diff.current.checked_sub(1).map(Message::DiffGoTo)),
+            button(text(self.with_keys("Next", Action::NextHunk))).on_press_maybe(
+                (diff.current + 1 < diff.hunks.len()).then_some(Message::DiffGoTo(diff.current + 1))
+            ),
+            button("Close").on_press(Message::CloseOverlay),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+
+        let title = |side: Side| {
+            let diff_side = diff.side(side);
+            let name = match &diff_side.source {
+                Source::File(path) => path.display().to_string(),
+                Source::Buffer(path) => format!("{} (editor)", path.display()),
+                Source::Version(path, label) => format!("{} ({})", path.display(), label),
+            };
+            let dirty = if diff_side.dirty { " *" } else { "" };
+            row![
+                text(format!("{}{}", name, dirty)).width(iced::Length::Fill),
+                button("Save").on_press_maybe(
+                    (diff_side.dirty && diff_side.writable).then_some(Message::DiffSave(side))
+                ),
+            ]
+            .spacing(10)
+            .width(iced::Length::Fill)
+        };
+        let sides = row![
+            title(Side::Left),
+            button("Copy →").on_press_maybe(has_hunk.then_some(Message::DiffCopyHunk(Side::Left))),
+            button("← Copy").on_press_maybe(has_hunk.then_some(Message::DiffCopyHunk(Side::Right))),
+            title(Side::Right),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+
+        let cell = |line: &Option<(usize, Vec<diff::Segment>)>, color: iced::Color| {
+            let mut children: Vec<iced::Element<'_, Message>> = Vec::new();
+            if let Some((number, segments)) = line {
+                children.push(
+                    text(format!("{:>5} ", number + 1))
+                        .font(iced::Font::MONOSPACE)
+                        .size(14)
+                        .into(),
+                );
+                for (segment, changed) in segments {
+                    let mut piece = text(segment.replace('\t', "    "))
+                        .font(iced::Font::MONOSPACE)
+                        .size(14);
+                    if *changed {
+                        piece = piece.style(iced::theme::Text::Color(color));
+                    }
+                    children.push(piece.into());
+                }
+            }
+            iced::widget::Row::with_children(children).width(iced::Length::Fill)
+        };
+        let mut rows = column![];
+        for line in &diff.rows {
+            if line.folded > 0 {
+                rows = rows.push(
+                    row![
+                        text("").width(20),
+                        text(format!("⋯ {} unchanged lines", line.folded)).size(14)
+                    ]
+                    .spacing(10)
+                    .height(DIFF_ROW_HEIGHT),
+                );
+                continue;
+            }
+            let marker = match line.hunk {
+                Some(hunk) if hunk == diff.current => "▶",
+                Some(_) => "•",
+                None => " ",
+            };
+            rows = rows.push(
+                row![
+                    text(marker).width(20),
+                    cell(&line.left, palette.danger),
+                    cell(&line.right, palette.success)
+                ]
+                .spacing(10)
+                .height(DIFF_ROW_HEIGHT),
+            );
+        }
+        container(column![
+            header,
+            sides,
+            scrollable(rows).id(diff_scroll_id())
+        ]
+        .spacing(10))
+        .padding(50)
+        .into()
+    }
+
+    // A button label with the keys for its action, if it has any
+    fn with_keys(&self, label: &str, action: Action) -> String {
+        match self.keymap.keys(action) {
+            keys if keys.is_empty() => label.to_string(),
+            keys => format!("{} ({})", label, keys),
+        }
+    }
+
+    fn commander_view(&self) -> iced::Element<'_, Message> {
+        let has_selection = self.pane(self.active_side).has_selection();
+        let toolbar = row![
+            button(text(self.with_keys("Copy to Other", Action::CopyToOther)))
+                .on_press_maybe(has_selection.then_some(Message::CopyToOther)),
+            button(text(self.with_keys("Move to Other", Action::MoveToOther)))
+                .on_press_maybe(has_selection.then_some(Message::MoveToOther)),
+            button(text(self.with_keys("Swap Panes", Action::SwapPanes))).on_press(Message::SwapPanes),
+            text(self.task_status.as_deref().unwrap_or("")),
+            horizontal_space(),
+            button(text(self.with_keys("Single Pane", Action::DualPane))).on_press(Message::DualPane),
+        ]
+        .spacing(10);
+        column![
+            toolbar,
+            row![self.pane_view(Side::Left), self.pane_view(Side::Right)].spacing(10)
+        ]
+        .spacing(10)
+        .padding(10)
+        .into()
+    }
+
+    fn extract(&mut self, archive_path: PathBuf, destination: PathBuf) -> Command<Message> {
+        self.task_status = Some(format!("Extracting {}", archive_path.display()));
+        iced::command::channel(100, move |progress| {
+            archive::extract_archive(archive_path, destination, progress)
+        })
+        .map(Message::Extracting)
+    }
+
+    fn compress_view(&self) -> iced::Element<'_, Message> {
+        let mut names = column![].spacing(2);
+        for source in &self.compress_sources {
+            names = names.push(text(source.file_name().unwrap_or_default().to_string_lossy()));
+        }
+        let name_input = text_input("Archive name", &self.compress_name)
+            .on_input(Message::CompressName)
+            .padding(10)
+            .width(300);
+        let format_list = PickList::new(
+            ArchiveFormat::ALL,
+            Some(self.compress_format),
+            Message::CompressFormat,
+        );
+        let level_slider = slider(0..=9, self.compress_level, Message::CompressLevel).width(300);
+        let can_create = !self.compress_sources.is_empty() && !self.compress_name.trim().is_empty();
+        let create_button = button(text("Create").size(30))
+            .on_press_maybe(can_create.then_some(Message::ConfirmCompress));
+        let cancel_button = button(text("Cancel").size(30)).on_press(Message::CancelCompress);
+        container(
+            column![
+                text("Compress").size(40),
+                scrollable(names).height(200),
+                row![name_input, text("."), format_list].spacing(5),
+                row![
+                    text(format!("Compression level {}", self.compress_level)),
+                    level_slider
+                ]
+                .spacing(10),
+                row![create_button, cancel_button].spacing(20),
+            ]
+            .spacing(15),
+        )
+        .padding(50)
+        .into()
+    }
+
+    fn rehash(&mut self) -> Command<Message> {
+        let Some(Checksums::Hashes {
+            sources, running, ..
+        }) = &mut self.checksums
+        else {
+            return Command::none();
+        };
+        *running = true;
+        Command::perform(
+            checksum::hash_files(sources.clone(), self.checksum_algorithm),
+            Message::ChecksumsHashed,
+        )
+    }
+
+    fn checksums_view(&self) -> iced::Element<'_, Message> {
+        let Some(checksums) = &self.checksums else {
+            return text("Nothing to check").into();
+        };
+        let idle = !checksums.running();
+        let mut rows = column![].spacing(4);
+        let (title, status) = match checksums {
+            Checksums::Hashes { hashes, .. } => {
+                for (path, hash) in hashes {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    rows = rows.push(
+                        row![
+                            text(name).width(iced::Length::FillPortion(1)),
+                            text(hash).width(iced::Length::FillPortion(3)),
+                            button("Copy").on_press(Message::CopyChecksum(hash.clone())),
+                        ]
+                        .spacing(10)
+                        .align_items(iced::Alignment::Center),
+                    );
+                }
+                ("Checksums".to_string(), String::new())
+            }
+            Checksums::Verify {
+                manifest, results, ..
+            } => {
+                let mut failed = 0;
+                for result in results {
+                    let label = match result.outcome {
+                        Outcome::Passed => "OK",
+                        Outcome::Failed => "FAILED",
+                        Outcome::Missing => "MISSING",
+                    };
+                    failed += usize::from(result.outcome != Outcome::Passed);
+                    rows = rows.push(
+                        row![text(label).width(100), text(&result.relative)].spacing(10),
+                    );
+                }
+                let status = if !idle {
+                    String::new()
+                } else if failed == 0 {
+                    format!("Passed: all {} files match", results.len())
+                } else {
+                    format!("Failed: {} of {} files don't match", failed, results.len())
+                };
+                (format!("Verify {}", manifest.display()), status)
+            }
+        };
+        let mut header = row![text(title).size(30), horizontal_space()]
+            .spacing(10)
+            .align_items(iced::Alignment::Center);
+        // Hidden while hashing, so a late result can't be shown under another algorithm
+        if let (Checksums::Hashes { .. }, true) = (checksums, idle) {
+            header = header.push(PickList::new(
+                HashAlgorithm::ALL,
+                Some(self.checksum_algorithm),
+                Message::ChecksumAlgorithm,
+            ));
+        }
+        let header = header.push(button("Close").on_press(Message::CloseOverlay));
+        let status = if idle { status } else { "Hashing...".to_string() };
+        container(column![header, text(status), scrollable(rows)].spacing(10))
+            .padding(50)
+            .into()
+    }
+
+    fn snapshots_view(&self) -> iced::Element<'_, Message> {
+        let Some(snapshots) = &self.snapshots else {
+            return text("No snapshots").into();
+        };
+        let idle = !snapshots.running;
+        let header = row![
+            text("Snapshots").size(30),
+            horizontal_space(),
+            button("Take Snapshot").on_press_maybe(idle.then_some(Message::TakeSnapshot)),
+            button("Close").on_press(Message::CloseOverlay),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+        let mut list = column![].spacing(4);
+        for (index, info) in snapshots.list.iter().enumerate() {
+            let label = if snapshots.compared == Some(index) {
+                format!("▶ {}", info.label())
+            } else {
+                info.label()
+            };
+            list = list.push(
+                row![
+                    text(label).width(iced::Length::FillPortion(2)),
+                    text(format!("{} files, {}", info.files, format_size(info.size)))
+                        .width(iced::Length::FillPortion(2)),
+                    button("Compare").on_press_maybe(idle.then_some(Message::CompareSnapshot(index))),
+                    button("Delete").on_press_maybe(idle.then_some(Message::DeleteSnapshot(index))),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center),
+            );
+        }
+        let status = if !idle {
+            "Working...".to_string()
+        } else if snapshots.list.is_empty() {
+            "No snapshots of this folder yet".to_string()
+        } else if snapshots.compared.is_none() {
+            "Compare a snapshot to see what changed since".to_string()
+        } else if snapshots.changes.is_empty() {
+            "The folder is the same as the snapshot".to_string()
+        } else {
+            format!("{} changes since the snapshot", snapshots.changes.len())
+        };
+        let mut changes = column![].spacing(4);
+        for (index, change) in snapshots.changes.iter().enumerate() {
+            let kind = match change.kind {
+                ChangeKind::Added => "added",
+                ChangeKind::Removed => "removed",
+                ChangeKind::Changed => "changed",
+            };
+            let name = if change.is_dir {
+                format!("{}/", change.path)
+            } else {
+                change.path.clone()
+            };
+            changes = changes.push(
+                row![
+                    text(kind).width(100),
+                    text(name).width(iced::Length::Fill),
+                    button("Restore").on_press_maybe(idle.then_some(Message::RestoreChange(index))),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center),
+            );
+        }
+        let restore_all = row![
+            text(status),
+            horizontal_space(),
+            button("Restore All").on_press_maybe(
+                (idle && !snapshots.changes.is_empty()).then_some(Message::RestoreSnapshot)
+            ),
+        ]
+        .align_items(iced::Alignment::Center);
+        container(
+            column![
+                header,
+                text(snapshots.root.display().to_string()),
+                list,
+                restore_all,
+                scrollable(changes)
+            ]
+            .spacing(10),
+        )
+        .padding(50)
+        .into()
+    }
+
+    fn recovery_view(&self) -> iced::Element<'_, Message> {
+        let header = row![
+            text("Recover Unsaved Edits").size(30),
+            horizontal_space(),
+            button("Later").on_press(Message::RecoveryDone),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+        let mut rows = column![].spacing(4);
+        for (index, swap) in self.swaps.iter().enumerate() {
+            rows = rows.push(
+                row![
+                    text(swap.path.display().to_string()).width(iced::Length::FillPortion(3)),
+                    text(swap.label()).width(iced::Length::FillPortion(1)),
+                    button("Recover").on_press(Message::RecoverSwap(index)),
+                    button("Compare").on_press(Message::CompareSwap(index)),
+                    button("Discard").on_press(Message::DiscardSwap(index)),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center),
+            );
+        }
+        container(
+            column![
+                header,
+                text("These files had edits that were never saved when the app last stopped."),
+                scrollable(rows)
+            ]
+            .spacing(10),
+        )
+        .padding(50)
+        .into()
+    }
+
+    fn history_view(&self) -> iced::Element<'_, Message> {
+        let Some(history) = &self.history else {
+            return text("No history").into();
+        };
+        let header = row![
+            text("History").size(30),
+            horizontal_space(),
+            button("Close").on_press(Message::CloseOverlay),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+        let status = if history.loading {
+            "Loading...".to_string()
+        } else if history.versions.is_empty() {
+            "No earlier versions, one is kept every time the file is saved".to_string()
+        } else {
+            format!("{} earlier versions", history.versions.len())
+        };
+        let mut rows = column![].spacing(4);
+        for (index, version) in history.versions.iter().enumerate() {
+            rows = rows.push(
+                row![
+                    text(version.label()).width(iced::Length::FillPortion(2)),
+                    text(format_size(version.size)).width(iced::Length::FillPortion(1)),
+                    button("Compare").on_press(Message::HistoryCompare(index)),
+                    button("Restore").on_press(Message::HistoryRestore(index)),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center),
+            );
+        }
+        container(
+            column![
+                header,
+                text(history.file.display().to_string()),
+                text(status),
+                scrollable(rows)
+            ]
+            .spacing(10),
+        )
+        .padding(50)
+        .into()
+    }
+
+    fn disk_usage_view(&self) -> iced::Element<'_, Message> {
+        let Some(usage) = &self.disk_usage else {
+            return text("Nothing scanned").into();
+        };
+        let header = row![
+            text("Disk Usage").size(30),
+            horizontal_space(),
+            PickList::new(UsageSort::ALL, Some(usage.sort), Message::UsageSortBy),
+            button("Up").on_press_maybe((!usage.open.is_empty()).then_some(Message::UsageUp)),
+            button("Show in Listing").on_press(Message::UsageShowFolder),
+            button("Refresh").on_press_maybe((!usage.running).then_some(Message::DiskUsage)),
+            button("Close").on_press(Message::CloseOverlay),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+        let Some(current) = usage.current() else {
+            let status = if usage.running { "Scanning..." } else { "" };
+            return container(column![header, text(status)].spacing(10))
+                .padding(50)
+                .into();
+        };
+        let status = format!(
+            "{} in {}{}",
+            format_size(current.size),
+            usage.current_path().display(),
+            if usage.running { ", scanning again..." } else { "" }
+        );
+        // Bars are relative to the folder being shown, so its children always add up
+        let mut rows = column![].spacing(4);
+        for child in current.sorted(usage.sort) {
+            let percent = if current.size == 0 {
+                0.0
+            } else {
+                child.size as f32 * 100.0 / current.size as f32
+            };
+            let icon = if child.is_dir { "📁" } else { "📝" };
+            let name: iced::Element<'_, Message> = if child.is_dir {
+                button(text(format!("{}{}", icon, child.name)))
+                    .on_press(Message::UsageOpen(child.name.clone()))
+                    .style(iced::theme::Button::Text)
+                    .padding(0)
+                    .into()
+            } else {
+                text(format!("{}{}", icon, child.name)).into()
+            };
+            rows = rows.push(
+                row![
+                    container(name).width(iced::Length::FillPortion(3)),
+                    text(format_size(child.size)).width(100),
+                    text(format!("{:.1}%", percent)).width(60),
+                    progress_bar(0.0..=100.0, percent)
+                        .height(12)
+                        .width(iced::Length::FillPortion(2)),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center),
+            );
+        }
+        container(column![header, text(status), scrollable(rows)].spacing(10))
+            .padding(50)
+            .into()
+    }
+
+    // Actions are disabled while a search runs, so indices always match what is shown
+    fn duplicate_group(&self, group: usize) -> Option<&duplicates::DuplicateGroup> {
+        let found = self.duplicates.as_ref()?;
+        if found.running {
+            return None;
+        }
+        found.groups.get(group)
+    }
+
+    fn duplicates_view(&self) -> iced::Element<'_, Message> {
+        let Some(found) = &self.duplicates else {
+            return text("Nothing searched").into();
+        };
+        let idle = !found.running;
+        let header = row![
+            text("Find Duplicates").size(30),
+            horizontal_space(),
+            button("Refresh").on_press_maybe(idle.then_some(Message::FindDuplicates)),
+            button("Close").on_press(Message::CloseOverlay),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+        let status = if found.running {
+            "Searching...".to_string()
+        } else if found.groups.is_empty() {
+            "No duplicates".to_string()
+        } else {
+            let wasted: u64 = found.groups.iter().map(|group| group.wasted()).sum();
+            format!(
+                "{} groups, {} can be freed",
+                found.groups.len(),
+                format_size(wasted)
+            )
+        };
+        let mut groups = column![].spacing(15);
+        for (index, group) in found.groups.iter().enumerate() {
+            let mut copies = column![
+                row![
+                    text(format!(
+                        "{} copies of {}",
+                        group.paths.len(),
+                        format_size(group.size)
+                    ))
+                    .width(iced::Length::Fill),
+                    button("Trash Others")
+                        .on_press_maybe(idle.then_some(Message::TrashDuplicates(index))),
+                    button("Hard-Link Others")
+                        .on_press_maybe(idle.then_some(Message::LinkDuplicates(index))),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center)
+            ]
+            .spacing(2);
+            for (copy, path) in group.paths.iter().enumerate() {
+                let shown = path.strip_prefix(&found.root).unwrap_or(path);
+                copies = copies.push(
+                    checkbox(shown.display().to_string(), copy == group.keep)
+                        .on_toggle(move |_| Message::KeepDuplicate(index, copy)),
+                );
+            }
+            groups = groups.push(copies);
+        }
+        container(
+            column![
+                header,
+                text(format!("Under {}, the checked copy is kept", found.root.display())),
+                text(status),
+                scrollable(groups)
+            ]
+            .spacing(10),
+        )
+        .padding(50)
+        .into()
+    }
+
+    fn rename_view(&self) -> iced::Element<'_, Message> {
+        let Some(rename) = &self.bulk_rename else {
+            return text("Nothing to rename").into();
+        };
+        let preview = rename.preview();
+        let mut rows = column![].spacing(2);
+        let mut problems = 0;
+        let mut changes = 0;
+        match &preview {
+            Ok(preview) => {
+                for row in preview {
+                    problems += usize::from(row.problem.is_some());
+                    changes += usize::from(row.changed());
+                    rows = rows.push(
+                        row![
+                            text(&row.old).width(iced::Length::FillPortion(2)),
+                            text("→"),
+                            text(&row.new).width(iced::Length::FillPortion(2)),
+                            text(row.problem.unwrap_or("")).width(iced::Length::FillPortion(1)),
+                        ]
+                        .spacing(10),
+                    );
+                }
+            }
+            Err(error) => rows = rows.push(text(error)),
+        }
+        let status = match &preview {
+            Err(_) => String::new(),
+            Ok(_) if problems > 0 => format!("{} names to fix before renaming", problems),
+            Ok(_) => format!("{} of {} entries will be renamed", changes, rename.sources.len()),
+        };
+        let can_rename = preview.is_ok() && problems == 0 && changes > 0;
+        let rename_button = button(text("Rename").size(30))
+            .on_press_maybe(can_rename.then_some(Message::ConfirmRename));
+        let cancel_button = button(text("Cancel").size(30)).on_press(Message::CancelRename);
+        container(
+            column![
+                text("Bulk Rename").size(40),
+                row![
+                    text_input("Find", &rename.find)
+                        .on_input(Message::RenameFind)
+                        .width(250),
+                    text_input("Replace with", &rename.replace)
+                        .on_input(Message::RenameReplace)
+                        .width(250),
+                    checkbox("Regex", rename.regex).on_toggle(Message::RenameRegex),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center),
+                row![
+                    text_input("{name}{ext}", &rename.pattern)
+                        .on_input(Message::RenamePattern)
+                        .width(250),
+                    text("Start at"),
+                    text_input("1", &rename.start)
+                        .on_input(Message::RenameStart)
+                        .width(80),
+                    PickList::new(CaseChange::ALL, Some(rename.case), Message::RenameCase),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center),
+                text("Tokens: {name} {ext} {n} {n:3} {date}"),
+                scrollable(rows).height(iced::Length::Fill),
+                text(status),
+                row![rename_button, cancel_button].spacing(20),
+            ]
+            .spacing(15),
+        )
+        .padding(50)
+        .into()
+    }
+
+    fn filter_row(&self) -> iced::widget::Row<'_, Message> {
+        row![
+            checkbox("Show hidden", self.filter.show_hidden).on_toggle(Message::ToggleHidden),
+            PickList::new(KindFilter::ALL, Some(self.filter.kind), Message::FilterKind),
+            text_input("Extensions: rs, toml", &self.filter.extensions)
+                .on_input(Message::FilterExtensions)
+                .width(180),
+            text_input("Filter...", &self.filter.query)
+                .on_input(Message::FilterQuery)
+                .width(200),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center)
+    }
+
+    // Shortcuts only run on the pages where their action makes sense and has something to
+    // act on, so a key can never act on a folder or file that isn't there. Some actions,
+    // like Bulk Rename, Disk Usage, Sync or Compare, are only reached by key or the palette
+    fn action_message(&self, action: Action, mode: &Mode) -> Option<Message> {
+        let opened = matches!(mode, Mode::Opened);
+        let on_dir = matches!(mode, Mode::OnDir);
+        let browsing = (opened || on_dir) && self.dir.is_some();
+        let commander = matches!(mode, Mode::Commander);
+        let tab_count = self.tabs.len();
+        let message = match action {
+            Action::Save if opened => Message::Save,
+            Action::Delete if opened && self.active_tab.is_some() => Message::Delete,
+            Action::New if opened || on_dir => Message::New,
+            Action::OpenFile if opened || on_dir || matches!(mode, Mode::Start) => {
+                Message::OpenFile
+            }
+            Action::OpenFolder if opened || on_dir || matches!(mode, Mode::Start) => {
+                Message::OpenFolder
+            }
+            Action::Copy if browsing => Message::Copy,
+            Action::Cut if browsing => Message::Cut,
+            Action::Paste if on_dir && self.dir.is_some() && self.clipboard.is_some() => {
+                Message::Paste
+            }
+            Action::Refresh if on_dir && self.dir.is_some() => Message::Refresh,
+            Action::Back if commander => Message::PaneUp(self.active_side),
+            Action::Back if browsing && opened => Message::GoDirPage,
+            Action::Back if browsing => Message::BackFolder,
+            Action::Search => Message::QuickOpen,
+            Action::NextTab if (opened || on_dir) && tab_count > 0 => {
+                let next = self.active_tab.map_or(0, |index| (index + 1) % tab_count);
+                Message::SwitchTab(next)
+            }
+            Action::PreviousTab if (opened || on_dir) && tab_count > 0 => {
+                let previous = self
+                    .active_tab
+                    .map_or(tab_count - 1, |index| (index + tab_count - 1) % tab_count);
+                Message::SwitchTab(previous)
+            }
+            Action::CloseTab if opened => Message::CloseTab(self.active_tab?),
+            Action::Shortcuts if matches!(mode, Mode::Shortcuts) => Message::CloseOverlay,
+            Action::Shortcuts if !matches!(mode, Mode::QuickOpen | Mode::Palette) => {
+                Message::ShowShortcuts
+            }
+            Action::CommandPalette if matches!(mode, Mode::Palette) => Message::CloseOverlay,
+            Action::CommandPalette if !matches!(mode, Mode::QuickOpen | Mode::Shortcuts) => {
+                Message::CommandPalette
+            }
+            Action::CreateFolder if on_dir => Message::CreateFolder,
+            Action::ExtractHere if on_dir && self.listing.selected_archive().is_some() => {
+                Message::ExtractHere
+            }
+            Action::ExtractTo if on_dir && self.listing.selected_archive().is_some() => Message::ExtractTo,
+            Action::Compress if on_dir && self.can_compress() => Message::Compress,
+            // Archive entries can't be renamed either
+            Action::BulkRename if on_dir && self.can_compress() => Message::BulkRename,
+            Action::FindDuplicates if browsing && self.dir_on_disk => Message::FindDuplicates,
+            Action::DiskUsage if browsing && self.dir_on_disk => Message::DiskUsage,
+            Action::Checksums if on_dir && self.can_compress() => Message::Checksums,
+            Action::WriteManifest if on_dir && self.dir_on_disk => Message::WriteManifest,
+            Action::VerifyManifest if on_dir && self.dir_on_disk => Message::VerifyManifest,
+            Action::TakeSnapshot if on_dir && self.dir_on_disk => Message::TakeSnapshot,
+            Action::Snapshots if on_dir && self.dir_on_disk => Message::Snapshots,
+            Action::ToggleHidden if on_dir => Message::ToggleHidden(!self.filter.show_hidden),
+            Action::ToggleVerifyCopies if on_dir || commander => Message::ToggleVerifyCopies,
+            Action::ToggleFollowLinks if on_dir || commander => Message::ToggleFollowLinks,
+            Action::ToggleAutoSave => Message::ToggleAutoSave,
+            // Links can't point into an archive or be made inside one
+            Action::PasteSymlink | Action::PasteHardLink
+                if on_dir && self.dir_on_disk && self.clipboard_on_disk =>
+            {
+                Message::PasteLink(action == Action::PasteHardLink)
+            }
+            Action::ToggleToolbar if opened => Message::Show,
+            Action::ChangeTheme if opened => Message::GoThemePage,
+            Action::DualPane if commander || (on_dir && self.listing.root.is_some()) => {
+                Message::DualPane
+            }
+            Action::CopyToOther if commander => Message::CopyToOther,
+            Action::MoveToOther if commander => Message::MoveToOther,
+            Action::SwapPanes if commander => Message::SwapPanes,
+            Action::CompareFiles if commander || on_dir => Message::CompareFiles,
+            Action::CompareWithSaved if opened && self.active_tab.is_some() => {
+                Message::CompareWithSaved
+            }
+            Action::FileHistory if opened && self.active_tab.is_some() => Message::FileHistory,
+            Action::NextHunk | Action::PreviousHunk if matches!(mode, Mode::Diff) => {
+                let diff = self.text_diff.as_ref()?;
+                let index = if action == Action::NextHunk {
+                    diff.current + 1
+                } else {
+                    diff.current.checked_sub(1)?
+                };
+                Message::DiffGoTo(index)
+            }
+            Action::CompareFolders if commander || (on_dir && self.listing.root.is_some()) => {
+                Message::CompareFolders
+            }
+            Action::SyncFolders if commander || (on_dir && self.listing.root.is_some()) => {
+                Message::SyncFolders
+            }
+            _ => return None,
+        };
+        Some(message)
+    }
+
+    fn link_mode(&self) -> LinkMode {
+        if self.follow_links {
+            LinkMode::Follow
+        } else {
+            LinkMode::Preserve
+        }
+    }
+
+    // Archives are read-only, so there is nothing on disk to compress inside one
+    fn can_compress(&self) -> bool {
+        self.listing.has_selection() && self.listing.root.is_some() && self.listing.archive.is_none()
+    }
+
+    // Commands the page behind the palette can run, recent ones first until something
+    // is typed and then by fuzzy score
+    fn palette_actions(&self) -> Vec<Action> {
+        let available: Vec<Action> = Action::ALL
+            .into_iter()
+            .filter(|action| *action != Action::CommandPalette)
+            .filter(|action| self.action_message(*action, &self.previous_mode).is_some())
+            .collect();
+        let query = self.palette_query.trim();
+        if query.is_empty() {
+            let recent = self
+                .recent_actions
+                .iter()
+                .copied()
+                .filter(|action| available.contains(action));
+            let rest = available
+                .iter()
+                .copied()
+                .filter(|action| !self.recent_actions.contains(action));
+            return recent.chain(rest).collect();
+        }
+        let mut scored: Vec<(i64, Action)> = available
+            .into_iter()
+            .filter_map(|action| {
+                search::fuzzy_score(query, &action.to_string()).map(|score| (score, action))
+            })
+            .collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, action)| action).collect()
+    }
+
+    fn palette_view(&self) -> iced::Element<'_, Message> {
+        let input = text_input("Type a command", &self.palette_query)
+            .id(palette_id())
+            .on_input(Message::PaletteQuery)
+            .on_submit(Message::PaletteSubmit)
+            .size(25)
+            .padding(10);
+        let show_recent = self.palette_query.trim().is_empty();
+        let mut results = column![].spacing(2);
+        for action in self.palette_actions() {
+            let recent = if show_recent && self.recent_actions.contains(&action) {
+                "recent    "
+            } else {
+                ""
+            };
+            results = results.push(
+                button(row![
+                    text(action.to_string()),
+                    horizontal_space(),
+                    text(recent),
+                    text(self.keymap.keys(action))
+                ])
+                .on_press(Message::RunAction(action))
+                .width(iced::Length::Fill),
+            );
+        }
+        let close_button = button("Close").on_press(Message::CloseOverlay);
+        container(column![
+            row![text("Command Palette").size(30), horizontal_space(), close_button],
+            input,
+            scrollable(results)
+        ]
+        .spacing(10))
+        .padding(50)
+        .into()
+    }
+
+    fn shortcuts_view(&self) -> iced::Element<'_, Message> {
+        let mut list = column![].spacing(5);
+        for action in Action::ALL {
+            let keys = self.keymap.keys(action);
+            list = list.push(row![
+                text(action.to_string()).width(250),
+                text(if keys.is_empty() { "-".to_string() } else { keys })
+            ]);
+        }
+        let hint = keymap::keymap_file()
+            .map(|path| format!("Change these in {}", path.display()))
+            .unwrap_or_default();
+        let close_button = button("Close").on_press(Message::CloseOverlay);
+        container(column![
+            row![text("Keyboard Shortcuts").size(30), horizontal_space(), close_button],
+            scrollable(list),
+            text(hint)
+        ]
+        .spacing(10))
+        .padding(50)
+        .into()
+    }
+
+    // Built-in themes keep iced's editor style, custom ones may override its colours
+    fn editor_style(&self) -> iced::theme::TextEditor {
+        match self.themes.find(&self.theme.to_string()) {
+            Some(custom) if custom.editor != themes::EditorColors::default() => {
+                iced::theme::TextEditor::Custom(Box::new(themes::EditorStyle(custom.editor)))
+            }
+            _ => iced::theme::TextEditor::Default,
+        }
+    }
+
+    // The folder that quick open indexes, the parent when a file is opened
+    fn current_root(&self) -> Option<PathBuf> {
+        let dir = self.dir.clone()?;
+        if dir.is_dir() {
+            Some(dir)
+        } else {
+            dir.parent().map(Path::to_path_buf)
+        }
+    }
+
+    fn current_settings(&self) -> UserSettings {
+        // A file open in the editor counts as being in its folder
+        let last_folder = self.listing.root.clone().or_else(|| self.current_root());
+        UserSettings {
+            theme: self.theme.to_string(),
+            last_folder,
+            tabs: self.tabs.iter().map(|tab| tab.path.clone()).collect(),
+            active_tab: self.active_tab,
+            window_width: self.window_size.0,
+            window_height: self.window_size.1,
+            show_hidden: self.filter.show_hidden,
+            kind_filter: self.filter.kind.to_string(),
+            compress_level: self.compress_level,
+            verify_copies: self.verify_copies,
+            follow_links: self.follow_links,
+            history_max_versions: self.history_retention.max_versions,
+            history_max_days: self.history_retention.max_days,
+            auto_save_on_focus_loss: self.auto_save_on_focus_loss,
+            auto_save_interval: self.auto_save_interval,
+            recent_commands: self
+                .recent_actions
+                .iter()
+                .map(|action| action.name().to_string())
+                .collect(),
+        }
+    }
+
+    fn quick_open_view(&self) -> iced::Element<'_, Message> {
+        let input = text_input("Search files, or use a glob like **/*.rs", &self.quick_query)
+            .id(quick_open_id())
+            .on_input(Message::QuickQuery)
+            .on_submit(Message::QuickSubmit)
+            .size(25)
+            .padding(10);
+        let status = if self.indexing {
+            text("Indexing...")
+        } else if let Some(error) = &self.index_error {
+            text(format!("Index failed: {}", error))
+        } else {
+            text(format!("{} entries indexed", self.index.len()))
+        };
+        let mut results = column![].spacing(2);
+        for entry in search::search(&self.index, &self.quick_query, 50) {
+            let icon = if entry.is_dir { "📁" } else { "📝" };
+            results = results.push(
+                button(text(format!("{}{}", icon, entry.relative)))
+                    .on_press(Message::QuickPick(entry.path.clone(), entry.is_dir))
+                    .width(iced::Length::Fill),
+            );
+        }
+        let close_button = button("Close").on_press(Message::CloseOverlay);
+        container(column![
+            row![text("Quick Open").size(30), horizontal_space(), close_button],
+            input,
+            status,
+            scrollable(results)
+        ]
+        .spacing(10))
+        .padding(50)
+        .into()
+    }
+}
+
+fn quick_open_id() -> text_input::Id {
+    text_input::Id::new("quick_open")
+}
+
+fn diff_scroll_id() -> scrollable::Id {
+    scrollable::Id::new("text_diff")
+}
+
+const DIFF_ROW_HEIGHT: f32 = 20.0;
+
+// The path and text of each side, as read_file gives them
+type DiffText = (PathBuf, Arc<String>);
+
+// How often unsaved buffers are written to their swap files
+const SWAP_INTERVAL: Duration = Duration::from_secs(10);
+
+fn load_diff(left: PathBuf, right: PathBuf) -> Command<Message> {
+    Command::perform(
+        async move { Ok((read_file(left).await?, read_file(right).await?)) },
+        Message::DiffFilesLoaded,
+    )
+}
+
+fn palette_id() -> text_input::Id {
+    text_input::Id::new("command_palette")
+}
+
+const RECENT_ACTIONS: usize = 5;
+
+fn handle_event(event: Event, status: event::Status) -> Option<Message> {
+    match event {
+        Event::Window(_, iced::window::Event::Resized { width, height }) => {
+            Some(Message::WindowResized(width, height))
+        }
+        Event::Window(_, iced::window::Event::CloseRequested) => Some(Message::CloseRequested),
+        Event::Window(_, iced::window::Event::Unfocused) => Some(Message::FocusLost),
+        Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) => match key.as_ref() {
+            keyboard::Key::Named(keyboard::key::Named::Escape) => Some(Message::CloseOverlay),
+            // Plain keys typed into an editor or input stay there, the keymap only sees
+            // them when no widget used them
+            _ if status == event::Status::Captured && !(modifiers.command() || modifiers.alt()) => {
+                None
+            }
+            _ => Some(Message::Shortcut(key, modifiers)),
+        },
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Error {
+    DClosed,
+    IOErr(io::ErrorKind),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DClosed => write!(f, "dialog closed"),
+            Error::IOErr(kind) => write!(f, "{}", io::Error::from(*kind)),
+        }
+    }
+}
+
+// The folder the program was started from, or the crate folder if that is unknown
+fn default_file() -> PathBuf {
+    std::env::current_dir().unwrap_or_else(|_| PathBuf::from(env!("CARGO_MANIFEST_DIR")))
+}
+
+async fn read_file(path: PathBuf) -> Result<(PathBuf, Arc<String>), Error> {
+    if let Some((zip_path, inner)) = archive::split_archive_path(&path) {
+        let content = archive::read_archive_file(zip_path, inner).await?;
+        return Ok((path, content));
+    }
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map(Arc::new)
+        .map_err(|error| error.kind())
+        .map_err(Error::IOErr)?;
+
+    Ok((path, content))
+}
+
+async fn read_directory(path: PathBuf) -> Result<(PathBuf, Vec<FileType>), Error> {
+    if !path.is_dir() {
+        if let Some((zip_path, inner)) = archive::split_archive_path(&path) {
+            let entries = archive::read_archive_directory(zip_path, inner).await?;
+            return Ok((path, entries));
+        }
+    }
+    let mut handle = tokio::fs::read_dir(&path)
+        .await
+        .map_err(|error| error.kind())
+        .map_err(Error::IOErr)?;
+
+    let mut fsname = Vec::new();
+    while let Some(file) = handle.next_entry().await.unwrap_or(None) {
+        let file_name = file.file_name().to_string_lossy().to_string();
+        // Not followed, so a link is listed as a link rather than as what it points to
+        let Ok(file_type) = file.file_type().await else {
+            continue;
+        };
+        if file_type.is_symlink() {
+            let target = fs::read_link(file.path()).await.unwrap_or_default();
+            let is_dir = fs::metadata(file.path())
+                .await
+                .is_ok_and(|metadata| metadata.is_dir());
+            fsname.push(FileType::Link {
+                name: file_name,
+                target,
+                is_dir,
+            });
+        } else if file_type.is_dir() {
+            fsname.push(FileType::Dir(file_name))
+        } else {
+            fsname.push(FileType::File(file_name));
+        }
+    }
+
+    Ok((path, fsname))
+}
+
+async fn read_file_directory(path: PathBuf) -> Result<(PathBuf, Vec<FileType>), Error> {
+    let mut file_path = path.clone();
+    file_path.pop();
+    read_directory(file_path).await
+}
+
+async fn create_folder(path: Option<PathBuf>) -> Result<PathBuf, Error> {
+    let path = if let Some(path) = path {
+        if path.is_dir() {
+            rfd::AsyncFileDialog::new()
+                .set_title("Choose Folder Name")
+                .save_file()
+                .await
+                .ok_or(Error::DClosed)?
+                .path()
+                .to_owned()
+        } else {
+            path
+        }
+    } else {
+        rfd::AsyncFileDialog::new()
+            .set_title("Choost Folder Name")
+            .save_file()
+            .await
+            .ok_or(Error::DClosed)?
+            .path()
+            .to_owned()
+    };
+    tokio::fs::create_dir(&path)
+        .await
+        .map_err(|error| error.kind())
+        .map_err(Error::IOErr)?;
     Ok(path)
 }
 
@@ -612,16 +3839,76 @@ async fn open_folder() -> Result<(PathBuf, Vec<FileType>), Error> {
     read_directory(handle.path().to_owned()).await
 }
 
-async fn open_file() -> Result<(PathBuf, Arc<String>), Error> {
+// Reads files one after another so tabs open in the order given
+async fn read_files(paths: Vec<PathBuf>) -> Vec<Result<(PathBuf, Arc<String>), Error>> {
+    let mut results = Vec::new();
+    for path in paths {
+        results.push(read_file(path).await);
+    }
+    results
+}
+
+async fn pick_folder() -> Result<PathBuf, Error> {
+    let handle = rfd::AsyncFileDialog::new()
+        .set_title("Select Folder")
+        .pick_folder()
+        .await
+        .ok_or(Error::DClosed)?;
+    Ok(handle.path().to_owned())
+}
+
+async fn pick_file() -> Result<PathBuf, Error> {
     let handle = rfd::AsyncFileDialog::new()
         .set_title("Select File")
         .pick_file()
         .await
         .ok_or(Error::DClosed)?;
-    read_file(handle.path().to_owned()).await
+    Ok(handle.path().to_owned())
+}
+
+// "name.zip" inside folder, or "name (2).zip" and so on when that is taken
+fn unique_path(folder: &Path, stem: &str, extension: &str) -> PathBuf {
+    let mut path = folder.join(format!("{}.{}", stem, extension));
+    let mut count = 2;
+    while path.exists() {
+        path = folder.join(format!("{} ({}).{}", stem, count, extension));
+        count += 1;
+    }
+    path
+}
+
+// Sizes in binary units, as file managers show them
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+// Archives are browsed like folders, everything else is loaded into the editor
+fn open_entry(path: PathBuf, is_dir: bool) -> Command<Message> {
+    if is_dir || archive::is_archive(&path) {
+        Command::perform(read_directory(path), Message::LoadFolder)
+    } else {
+        Command::perform(read_file(path), Message::LoadFile)
+    }
 }
 
-async fn save_file(path: Option<PathBuf>, text: String) -> Result<PathBuf, Error> {
+// What the file held before is kept in its history first, a history that can't be
+// written doesn't stop the save. The new text replaces the file in one step, see atomic.rs
+async fn save_file(
+    path: Option<PathBuf>,
+    text: String,
+    retention: Retention,
+) -> Result<PathBuf, Error> {
     let path = if let Some(path) = path {
         if path.is_dir() {
             rfd::AsyncFileDialog::new()
@@ -644,10 +3931,10 @@ async fn save_file(path: Option<PathBuf>, text: String) -> Result<PathBuf, Error
             .to_owned()
     };
 
-    tokio::fs::write(&path, &text)
-        .await
-        .map_err(|error| error.kind())
-        .map_err(Error::IOErr)?;
+    if let Err(error) = history::record(path.clone(), retention).await {
+        eprintln!("rust-vfs: no history kept for {}: {}", path.display(), error);
+    }
+    atomic::write(path.clone(), text.into_bytes()).await?;
 
     Ok(path)
 }
@@ -687,24 +3974,95 @@ async fn delete_file(path: Option<PathBuf>) -> Result<PathBuf, Error> {
     Ok(newpath)
 }
 
-async fn copy_file(ogfile: &PathBuf, destination: &PathBuf) -> Result<PathBuf, Error> {
-    let mut dest_path = destination.clone();
-    dest_path.push(ogfile.file_name().unwrap());
+// Where a copy of `source` goes in the folder. Pasting into the source's own folder gives
+// the copy a free name like "notes (copy).txt" instead of copying the file onto itself
+fn copy_target(source: &Path, destination: &Path) -> PathBuf {
+    let name = source.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let target = destination.join(&name);
+    if !links::same_file(source, &target) {
+        return target;
+    }
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 && !source.is_dir() => name.split_at(dot),
+        _ => (name.as_str(), ""),
+    };
+    (1..)
+        .map(|n| match n {
+            1 => destination.join(format!("{} (copy){}", stem, ext)),
+            n => destination.join(format!("{} (copy {}){}", stem, n, ext)),
+        })
+        .find(|candidate| std::fs::symlink_metadata(candidate).is_err())
+        .unwrap_or(target)
+}
+
+async fn copy_file(ogfile: &Path, destination: &Path) -> Result<PathBuf, Error> {
+    let dest_path = copy_target(ogfile, destination);
     tokio::fs::copy(ogfile, dest_path.clone())
         .await
         .map_err(|error| error.kind())
         .map_err(Error::IOErr)?;
+    copy_metadata(ogfile, &dest_path).await?;
     Ok(dest_path)
 }
 
-async fn copy_foldder(ogfolder: &PathBuf, destination: &PathBuf) -> Result<PathBuf, Error> {
-    let mut dest_path = destination.clone();
-    dest_path.push(ogfolder.file_name().unwrap());
+// The access and modification times, then permissions and extended attributes. Neither of
+// those changes the times, and a file made read-only can't be opened to set them on Windows.
+// A copy whose times couldn't be kept still has all its data, so that is only a warning
+async fn copy_metadata(source: &Path, target: &Path) -> Result<(), Error> {
+    let (source, target) = (source.to_path_buf(), target.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        let meta = std::fs::metadata(&source)?;
+        let mut times = std::fs::FileTimes::new().set_modified(meta.modified()?);
+        if let Ok(accessed) = meta.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        if let Err(error) = set_times(&target, times) {
+            eprintln!("rust-vfs: keeping the times of {} failed: {}", target.display(), error);
+        }
+        std::fs::set_permissions(&target, meta.permissions())?;
+        #[cfg(unix)]
+        atomic::copy_xattrs(&source, &target);
+        Ok(())
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+// Windows only changes times through a handle with write access, and only opens a folder
+// with backup semantics. Elsewhere a read-only handle does, which also works for folders
+fn set_times(path: &Path, times: std::fs::FileTimes) -> io::Result<()> {
+    #[cfg(windows)]
+    let file = {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(path)?
+    };
+    #[cfg(not(windows))]
+    let file = std::fs::File::open(path)?;
+    file.set_times(times)
+}
+
+async fn copy_foldder(
+    ogfolder: &Path,
+    destination: &Path,
+    links: LinkMode,
+) -> Result<PathBuf, Error> {
+    let dest_path = copy_target(ogfolder, destination);
     fs::create_dir_all(&dest_path)
         .await
         .map_err(|error| Error::IOErr(error.kind()))?;
     let mut stack = VecDeque::new();
-    stack.push_back((ogfolder.clone(), dest_path.clone()));
+    stack.push_back((ogfolder.to_path_buf(), dest_path.clone()));
+    let mut folders = Vec::new();
+
+    // Followed links can lead back to a folder already being copied, which would never end
+    let mut visited = HashSet::new();
+    visited.extend(fs::canonicalize(ogfolder).await.ok());
+    let mut hard_links = links::HardLinks::default();
 
     while let Some((current_source, current_dest)) = stack.pop_front() {
         let mut dir = fs::read_dir(&current_source)
@@ -714,20 +4072,149 @@ async fn copy_foldder(ogfolder: &PathBuf, destination: &PathBuf) -> Result<PathB
             let path = entry.path();
             let mut new_dest = current_dest.clone(); // reclone the current destination
             new_dest.push(path.file_name().unwrap()); // push the file name to the destination WORK SOMEHOW LMAFO
-            if path.is_dir() {
+            let meta = entry
+                .metadata()
+                .await
+                .map_err(|error| Error::IOErr(error.kind()))?;
+            if meta.file_type().is_symlink() && links == LinkMode::Preserve {
+                links::copy_symlink(&path, &new_dest).await?;
+            } else if path.is_dir() {
+                let real = fs::canonicalize(&path)
+                    .await
+                    .map_err(|error| Error::IOErr(error.kind()))?;
+                if !visited.insert(real) {
+                    continue;
+                }
                 fs::create_dir_all(&new_dest)
                     .await
                     .map_err(|error| Error::IOErr(error.kind()))?;
                 stack.push_back((path, new_dest));
+            } else if let Some(first) = hard_links
+                .first_copy(&meta, &new_dest)
+                .filter(|_| links == LinkMode::Preserve)
+            {
+                fs::hard_link(first, &new_dest)
+                    .await
+                    .map_err(|error| Error::IOErr(error.kind()))?;
             } else {
-                copy_file(&path, &new_dest).await?;
+                copy_file(&path, &current_dest).await?;
             }
         }
+        folders.push((current_source, current_dest));
+    }
+    // Copying into a folder changes its times, so folders get theirs once they are full,
+    // the deepest first
+    for (source, dest) in folders.iter().rev() {
+        copy_metadata(source, dest).await?;
     }
 
     Ok(dest_path)
 }
 
+// Copies a file, a folder or an archive entry into the destination folder
+async fn paste_into(og_path: &Path, dest_path: &Path, links: LinkMode) -> Result<PathBuf, Error> {
+    if let Some((zip_path, inner)) = archive::split_archive_path(og_path) {
+        archive::extract_entry(zip_path, inner, dest_path.to_path_buf()).await
+    } else if links == LinkMode::Preserve && links::is_symlink(og_path) {
+        let target = dest_path.join(og_path.file_name().unwrap_or_default());
+        links::copy_symlink(og_path, &target).await?;
+        Ok(target)
+    } else if og_path.is_dir() {
+        // Copying a folder into itself would keep finding the copy it is making
+        if dest_path.starts_with(og_path) {
+            return Err(Error::IOErr(io::ErrorKind::InvalidInput));
+        }
+        copy_foldder(og_path, dest_path, links).await
+    } else {
+        copy_file(og_path, dest_path).await
+    }
+}
+
+// Pastes and, with verify on, hashes the copy against its source. Archive entries are
+// extracted, not copied, so there is nothing to hash them against
+async fn paste_checked(
+    og_path: &Path,
+    dest_path: &Path,
+    verify: bool,
+    links: LinkMode,
+) -> Result<(PathBuf, Option<compare::CopyCheck>), Error> {
+    let target = paste_into(og_path, dest_path, links).await?;
+    if !verify || archive::split_archive_path(og_path).is_some() {
+        return Ok((target, None));
+    }
+    let check = compare::verify_copy(og_path.to_path_buf(), target.clone()).await?;
+    Ok((target, Some(check)))
+}
+
+// The same, but a copy that doesn't hash the same as its source is an error
+async fn paste_verified(
+    og_path: &Path,
+    dest_path: &Path,
+    verify: bool,
+    links: LinkMode,
+) -> Result<PathBuf, Error> {
+    match paste_checked(og_path, dest_path, verify, links).await? {
+        (_, Some(check)) if !check.mismatched.is_empty() => {
+            Err(Error::IOErr(io::ErrorKind::InvalidData))
+        }
+        (target, _) => Ok(target),
+    }
+}
+
+// Cut and Paste, a rename, or a copy and delete across drives. A name that is already taken
+// is an error, nothing is overwritten or merged. The source is only deleted once the copy is
+// complete, and verified when verify is on
+async fn move_into(
+    og_path: &Path,
+    dest_path: &Path,
+    verify: bool,
+    links: LinkMode,
+) -> Result<(), Error> {
+    if og_path.parent() == Some(dest_path) {
+        return Ok(());
+    }
+    let target = dest_path.join(og_path.file_name().unwrap_or_default());
+    if fs::symlink_metadata(&target).await.is_ok() {
+        return Err(Error::IOErr(io::ErrorKind::AlreadyExists));
+    }
+    match fs::rename(og_path, &target).await {
+        Ok(()) => return Ok(()),
+        Err(error) if error.kind() != io::ErrorKind::CrossesDevices => {
+            return Err(Error::IOErr(error.kind()));
+        }
+        Err(_) => {}
+    }
+    paste_verified(og_path, dest_path, verify, links).await?;
+    let removed = if og_path.is_dir() && !links::is_symlink(og_path) {
+        fs::remove_dir_all(og_path).await
+    } else {
+        fs::remove_file(og_path).await
+    };
+    removed.map_err(|error| Error::IOErr(error.kind()))
+}
+
 fn main() -> iced::Result {
-    FileSystem::run(Settings::default())
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if cli::is_command(&args) {
+        std::process::exit(cli::run(args));
+    }
+    let mut paths = Vec::new();
+    for arg in args {
+        let path = default_file().join(arg);
+        if path.exists() || archive::split_archive_path(&path).is_some() {
+            paths.push(path);
+        } else {
+            eprintln!("rust-vfs: {}: {}", path.display(), Error::IOErr(io::ErrorKind::NotFound));
+        }
+    }
+    let settings = settings::load();
+    FileSystem::run(Settings {
+        window: iced::window::Settings {
+            size: iced::Size::new(settings.window_width, settings.window_height),
+            exit_on_close_request: false,
+            ..iced::window::Settings::default()
+        },
+        flags: (paths, settings),
+        ..Settings::default()
+    })
 }
@@ -0,0 +1,79 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{archive, atomic, settings, Error};
+
+// The unsaved text of one buffer, kept until it is saved. Swap files still there at startup
+// were left by a crash, by quitting or by closing a tab with unsaved edits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Swap {
+    pub path: PathBuf,
+    pub text: String,
+    #[serde(skip, default = "SystemTime::now")]
+    pub written: SystemTime,
+}
+
+impl Swap {
+    pub fn label(&self) -> String {
+        archive::time_label(self.written)
+    }
+}
+
+fn recovery_dir() -> Option<PathBuf> {
+    settings::config_dir().map(|dir| dir.join("recovery"))
+}
+
+fn swap_file(path: &Path) -> Option<PathBuf> {
+    let key = path.to_string_lossy();
+    recovery_dir().map(|dir| dir.join(format!("{:016x}.toml", xxh3_64(key.as_bytes()))))
+}
+
+// Read once at startup, before anything writes new swap files. Broken ones are skipped
+pub fn load() -> Vec<Swap> {
+    let Some(entries) = recovery_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut swaps: Vec<Swap> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let mut swap: Swap = toml::from_str(&std::fs::read_to_string(&path).ok()?).ok()?;
+            swap.written = std::fs::metadata(&path).ok()?.modified().ok()?;
+            Some(swap)
+        })
+        .collect();
+    swaps.sort_by(|a, b| a.path.cmp(&b.path));
+    swaps
+}
+
+// Each buffer is written to its own swap file the same safe way files are saved
+pub async fn write(buffers: Vec<(PathBuf, String)>) -> Result<(), Error> {
+    let dir = recovery_dir().ok_or(Error::IOErr(io::ErrorKind::NotFound))?;
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|error| Error::IOErr(error.kind()))?;
+    for (path, text) in buffers {
+        let Some(file) = swap_file(&path) else {
+            continue;
+        };
+        let swap = Swap {
+            path,
+            text,
+            written: SystemTime::now(),
+        };
+        let text =
+            toml::to_string(&swap).map_err(|_| Error::IOErr(io::ErrorKind::InvalidData))?;
+        atomic::write(file, text.into_bytes()).await?;
+    }
+    Ok(())
+}
+
+// Missing swap files are fine, most buffers never had one
+pub async fn remove(paths: Vec<PathBuf>) {
+    for file in paths.iter().filter_map(|path| swap_file(path)) {
+        let _ = tokio::fs::remove_file(file).await;
+    }
+}
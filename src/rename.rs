@@ -0,0 +1,433 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use regex::Regex;
+
+use crate::{archive, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseChange {
+    Keep,
+    Lower,
+    Upper,
+    Title,
+}
+
+impl CaseChange {
+    pub const ALL: [CaseChange; 4] = [
+        CaseChange::Keep,
+        CaseChange::Lower,
+        CaseChange::Upper,
+        CaseChange::Title,
+    ];
+
+    fn apply(self, name: &str) -> String {
+        match self {
+            CaseChange::Keep => name.to_string(),
+            CaseChange::Lower => name.to_lowercase(),
+            CaseChange::Upper => name.to_uppercase(),
+            // Upper case after anything that isn't a letter or digit, lower case elsewhere
+            CaseChange::Title => {
+                let mut title = String::with_capacity(name.len());
+                let mut start = true;
+                for ch in name.chars() {
+                    if start {
+                        title.extend(ch.to_uppercase());
+                    } else {
+                        title.extend(ch.to_lowercase());
+                    }
+                    start = !ch.is_alphanumeric();
+                }
+                title
+            }
+        }
+    }
+}
+
+impl fmt::Display for CaseChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaseChange::Keep => write!(f, "Keep case"),
+            CaseChange::Lower => write!(f, "lower case"),
+            CaseChange::Upper => write!(f, "UPPER CASE"),
+            CaseChange::Title => write!(f, "Title Case"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenameSource {
+    pub name: String,
+    pub is_dir: bool,
+    pub modified: Option<SystemTime>,
+}
+
+// The Bulk Rename dialog. The preview is worked out again from these fields on every
+// change, the folder is only read once when the dialog opens
+#[derive(Debug)]
+pub struct BulkRename {
+    pub folder: PathBuf,
+    pub sources: Vec<RenameSource>,
+    // Every name in the folder, to catch renames onto entries that aren't selected
+    pub existing: BTreeSet<String>,
+    pub find: String,
+    pub replace: String,
+    pub regex: bool,
+    pub pattern: String,
+    pub start: String,
+    pub case: CaseChange,
+}
+
+#[derive(Debug, Clone)]
+pub struct PreviewRow {
+    pub old: String,
+    pub new: String,
+    pub problem: Option<&'static str>,
+}
+
+impl PreviewRow {
+    pub fn changed(&self) -> bool {
+        self.old != self.new
+    }
+}
+
+impl BulkRename {
+    pub fn new(folder: PathBuf, sources: Vec<RenameSource>, existing: BTreeSet<String>) -> Self {
+        Self {
+            folder,
+            sources,
+            existing,
+            find: String::new(),
+            replace: String::new(),
+            regex: false,
+            pattern: "{name}{ext}".to_string(),
+            start: "1".to_string(),
+            case: CaseChange::Keep,
+        }
+    }
+
+    // Find and replace and the case change work on the name without its extension, the
+    // pattern then puts the new name together from tokens:
+    // {name}, {ext} (with its dot), {n} or {n:3} for a zero-padded counter, {date}
+    pub fn preview(&self) -> Result<Vec<PreviewRow>, String> {
+        let find = if self.find.is_empty() {
+            None
+        } else if self.regex {
+            Some(Regex::new(&self.find).map_err(|error| error.to_string())?)
+        } else {
+            Some(Regex::new(&regex::escape(&self.find)).map_err(|error| error.to_string())?)
+        };
+        // With plain text the replacement is taken literally, $1 only means something in
+        // a regex
+        let replace = if self.regex {
+            self.replace.clone()
+        } else {
+            self.replace.replace('$', "$$")
+        };
+        let start: u64 = self
+            .start
+            .trim()
+            .parse()
+            .map_err(|_| format!("{:?} is not a start number", self.start))?;
+
+        let mut rows = Vec::with_capacity(self.sources.len());
+        for (index, source) in self.sources.iter().enumerate() {
+            let (stem, ext) = split_extension(&source.name, source.is_dir);
+            let stem = match &find {
+                Some(find) => find.replace_all(stem, replace.as_str()).into_owned(),
+                None => stem.to_string(),
+            };
+            let stem = self.case.apply(&stem);
+            let new = expand(&self.pattern, &stem, ext, start + index as u64, source.modified)?;
+            rows.push(PreviewRow {
+                old: source.name.clone(),
+                new: new.trim().to_string(),
+                problem: None,
+            });
+        }
+
+        let mut targets: HashMap<String, usize> = HashMap::new();
+        for row in &rows {
+            *targets.entry(row.new.clone()).or_default() += 1;
+        }
+        let olds: BTreeSet<&str> = rows.iter().map(|row| row.old.as_str()).collect();
+        let problems: Vec<Option<&'static str>> = rows
+            .iter()
+            .map(|row| {
+                if row.new.is_empty() {
+                    Some("empty name")
+                } else if row.new == "." || row.new == ".." || row.new.contains(['/', '\\']) {
+                    Some("not a valid name")
+                } else if targets[&row.new] > 1 {
+                    Some("same name twice")
+                } else if row.changed()
+                    && self.existing.contains(&row.new)
+                    && !olds.contains(row.new.as_str())
+                {
+                    Some("already exists")
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (row, problem) in rows.iter_mut().zip(problems) {
+            row.problem = problem;
+        }
+        Ok(rows)
+    }
+}
+
+// Folders and dotfiles like .gitignore have no extension
+fn split_extension(name: &str, is_dir: bool) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(dot) if !is_dir && dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    }
+}
+
+fn expand(
+    pattern: &str,
+    stem: &str,
+    ext: &str,
+    counter: u64,
+    modified: Option<SystemTime>,
+) -> Result<String, String> {
+    let mut name = String::new();
+    let mut rest = pattern;
+    while let Some(open) = rest.find('{') {
+        name.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('}') else {
+            return Err("a { in the pattern is never closed".to_string());
+        };
+        let token = &rest[open + 1..open + close];
+        match token {
+            "name" => name.push_str(stem),
+            "ext" => name.push_str(ext),
+            "n" => name.push_str(&counter.to_string()),
+            "date" => {
+                let (year, month, day, _) = modified
+                    .and_then(archive::calendar)
+                    .ok_or_else(|| "no modification date to use for {date}".to_string())?;
+                name.push_str(&format!("{:04}-{:02}-{:02}", year, month, day));
+            }
+            _ => {
+                let width: usize = token
+                    .strip_prefix("n:")
+                    .and_then(|width| width.parse().ok())
+                    .ok_or_else(|| format!("unknown token {{{}}}", token))?;
+                name.push_str(&format!("{:0width$}", counter, width = width));
+            }
+        }
+        rest = &rest[open + close + 1..];
+    }
+    name.push_str(rest);
+    Ok(name)
+}
+
+// The selected entries with their modification dates, and every name in their folder
+pub async fn load(
+    folder: PathBuf,
+    paths: Vec<PathBuf>,
+) -> Result<(Vec<RenameSource>, BTreeSet<String>), Error> {
+    tokio::task::spawn_blocking(move || {
+        let sources = paths
+            .iter()
+            .map(|path| {
+                let meta = std::fs::symlink_metadata(path)?;
+                Ok(RenameSource {
+                    name: path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                    is_dir: meta.is_dir(),
+                    modified: meta.modified().ok(),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let existing = std::fs::read_dir(&folder)?
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<BTreeSet<_>>>()?;
+        Ok((sources, existing))
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+// Every entry is first moved to a temporary name, so swaps and chains like a → b → c
+// work, then to its new name. When either pass fails, the entries already under their new
+// names go back to their temporary ones first, so none of them is in the way, and then
+// everything is put back under its old name
+pub async fn apply(folder: PathBuf, renames: Vec<(String, String)>) -> Result<usize, Error> {
+    let mut moved: Vec<(PathBuf, &str, &str)> = Vec::with_capacity(renames.len());
+    for (index, (old, new)) in renames.iter().enumerate() {
+        let temp = folder.join(format!(".rename-{}-{}", uuid::Uuid::new_v4(), index));
+        if let Err(error) = tokio::fs::rename(folder.join(old), &temp).await {
+            restore(&folder, &moved).await;
+            return Err(Error::IOErr(error.kind()));
+        }
+        moved.push((temp, old, new));
+    }
+    for (done, (temp, _, new)) in moved.iter().enumerate() {
+        let target = folder.join(new);
+        // Checked again, something may have been created since the preview
+        let result = match tokio::fs::symlink_metadata(&target).await {
+            Ok(_) => Err(io::Error::from(io::ErrorKind::AlreadyExists)),
+            Err(_) => tokio::fs::rename(temp, &target).await,
+        };
+        if let Err(error) = result {
+            for (temp, _, new) in moved[..done].iter().rev() {
+                let _ = tokio::fs::rename(folder.join(new), temp).await;
+            }
+            restore(&folder, &moved).await;
+            return Err(Error::IOErr(error.kind()));
+        }
+    }
+    Ok(moved.len())
+}
+
+// An old name something else has taken meanwhile is never replaced, that entry stays under
+// its temporary name
+async fn restore(folder: &Path, moved: &[(PathBuf, &str, &str)]) {
+    for (temp, old, _) in moved.iter().rev() {
+        let target = folder.join(old);
+        if tokio::fs::symlink_metadata(&target).await.is_ok() {
+            eprintln!(
+                "rust-vfs: {} is taken, {} keeps its temporary name {}",
+                target.display(),
+                old,
+                temp.display()
+            );
+            continue;
+        }
+        let _ = tokio::fs::rename(temp, target).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder_with(files: &[(&str, &str)]) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("rust-vfs-rename-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        for (name, text) in files {
+            std::fs::write(folder.join(name), text).unwrap();
+        }
+        folder
+    }
+
+    fn read(folder: &Path, name: &str) -> Option<String> {
+        std::fs::read_to_string(folder.join(name)).ok()
+    }
+
+    fn renames(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(old, new)| (old.to_string(), new.to_string()))
+            .collect()
+    }
+
+    fn dialog(names: &[&str], existing: &[&str]) -> BulkRename {
+        let sources = names
+            .iter()
+            .map(|name| RenameSource {
+                name: name.to_string(),
+                is_dir: false,
+                modified: None,
+            })
+            .collect();
+        let existing = existing.iter().map(|name| name.to_string()).collect();
+        BulkRename::new(PathBuf::from("."), sources, existing)
+    }
+
+    #[test]
+    fn expands_tokens() {
+        assert_eq!(expand("{name}-{n:3}{ext}", "photo", ".jpg", 7, None).unwrap(), "photo-007.jpg");
+        assert_eq!(expand("{n}_{name}", "a", "", 12, None).unwrap(), "12_a");
+        assert!(expand("{name", "a", "", 1, None).is_err());
+        assert!(expand("{what}", "a", "", 1, None).is_err());
+        assert!(expand("{date}", "a", "", 1, None).is_err());
+    }
+
+    #[test]
+    fn splits_extensions() {
+        assert_eq!(split_extension("archive.tar.gz", false), ("archive.tar", ".gz"));
+        assert_eq!(split_extension(".gitignore", false), (".gitignore", ""));
+        assert_eq!(split_extension("folder.d", true), ("folder.d", ""));
+    }
+
+    #[test]
+    fn preview_flags_problems() {
+        let mut rename = dialog(&["a.txt", "b.txt"], &["a.txt", "b.txt", "c.txt"]);
+        rename.pattern = "same{ext}".to_string();
+        let rows = rename.preview().unwrap();
+        assert!(rows.iter().all(|row| row.problem == Some("same name twice")));
+
+        rename.pattern = "{name}{ext}".to_string();
+        rename.find = "a".to_string();
+        rename.replace = "c".to_string();
+        let rows = rename.preview().unwrap();
+        assert_eq!(rows[0].new, "c.txt");
+        assert_eq!(rows[0].problem, Some("already exists"));
+        assert_eq!(rows[1].problem, None);
+    }
+
+    // Names the selection itself frees up can be taken, so swaps aren't flagged
+    #[test]
+    fn preview_allows_swaps() {
+        let mut rename = dialog(&["2", "1"], &["1", "2"]);
+        rename.pattern = "{n}".to_string();
+        let rows = rename.preview().unwrap();
+        assert_eq!((rows[0].new.as_str(), rows[1].new.as_str()), ("1", "2"));
+        assert!(rows.iter().all(|row| row.problem.is_none()));
+    }
+
+    #[test]
+    fn plain_text_replacement_is_literal() {
+        let mut rename = dialog(&["a.txt"], &["a.txt"]);
+        rename.find = "a".to_string();
+        rename.replace = "$1".to_string();
+        assert_eq!(rename.preview().unwrap()[0].new, "$1.txt");
+        rename.regex = true;
+        rename.find = "(a)".to_string();
+        rename.replace = "${1}b".to_string();
+        assert_eq!(rename.preview().unwrap()[0].new, "ab.txt");
+    }
+
+    #[tokio::test]
+    async fn applies_chains_and_swaps() {
+        let folder = folder_with(&[("a", "A"), ("b", "B"), ("x", "X"), ("y", "Y")]);
+        let done = apply(
+            folder.clone(),
+            renames(&[("a", "b"), ("b", "c"), ("x", "y"), ("y", "x")]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(done, 4);
+        assert_eq!(read(&folder, "a"), None);
+        assert_eq!(read(&folder, "b").as_deref(), Some("A"));
+        assert_eq!(read(&folder, "c").as_deref(), Some("B"));
+        assert_eq!(read(&folder, "x").as_deref(), Some("Y"));
+        assert_eq!(read(&folder, "y").as_deref(), Some("X"));
+        let _ = std::fs::remove_dir_all(folder);
+    }
+
+    // "c" shows up after the preview, so the chain fails half way and must be undone
+    // without the first rename clobbering anything
+    #[tokio::test]
+    async fn failed_chain_puts_everything_back() {
+        let folder = folder_with(&[("a", "A"), ("b", "B"), ("c", "C")]);
+        let result = apply(folder.clone(), renames(&[("a", "b"), ("b", "c")])).await;
+        assert!(result.is_err());
+        assert_eq!(read(&folder, "a").as_deref(), Some("A"));
+        assert_eq!(read(&folder, "b").as_deref(), Some("B"));
+        assert_eq!(read(&folder, "c").as_deref(), Some("C"));
+        assert_eq!(std::fs::read_dir(&folder).unwrap().count(), 3);
+        let _ = std::fs::remove_dir_all(folder);
+    }
+}
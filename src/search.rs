@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    pub path: PathBuf,
+    pub relative: String,
+    pub is_dir: bool,
+}
+
+// Walks everything under root so the quick open palette can match on relative paths
+pub async fn build_index(root: PathBuf) -> Result<(PathBuf, Vec<IndexEntry>), Error> {
+    let mut entries = Vec::new();
+    let mut stack = VecDeque::new();
+    stack.push_back(root.clone());
+
+    while let Some(current) = stack.pop_front() {
+        let mut dir = match tokio::fs::read_dir(&current).await {
+            Ok(dir) => dir,
+            Err(error) if current == root => return Err(Error::IOErr(error.kind())),
+            Err(_) => continue,
+        };
+        while let Some(entry) = dir.next_entry().await.unwrap_or(None) {
+            let path = entry.path();
+            let is_dir = entry
+                .file_type()
+                .await
+                .map(|kind| kind.is_dir())
+                .unwrap_or(false);
+            entries.push(IndexEntry {
+                relative: relative_name(&root, &path),
+                path: path.clone(),
+                is_dir,
+            });
+            if is_dir {
+                stack.push_back(path);
+            }
+        }
+    }
+
+    Ok((root, entries))
+}
+
+fn relative_name(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+pub fn is_glob(query: &str) -> bool {
+    query.contains(['*', '?', '['])
+}
+
+// Every query character has to appear in order, consecutive hits and hits at the start
+// of a path segment score higher and long candidates are penalised slightly
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+
+    let mut score = 0;
+    let mut qi = 0;
+    let mut last_hit: Option<usize> = None;
+    for (ci, ch) in candidate.iter().enumerate() {
+        if qi == query.len() {
+            break;
+        }
+        if *ch != query[qi] {
+            continue;
+        }
+        score += 1;
+        if last_hit.is_some_and(|last| last + 1 == ci) {
+            score += 5;
+        }
+        if ci == 0 || matches!(candidate[ci - 1], '/' | '_' | '-' | '.' | ' ') {
+            score += 8;
+        }
+        last_hit = Some(ci);
+        qi += 1;
+    }
+
+    if qi < query.len() {
+        return None;
+    }
+    Some(score * 10 - candidate.len() as i64)
+}
+
+pub fn search<'a>(index: &'a [IndexEntry], query: &str, limit: usize) -> Vec<&'a IndexEntry> {
+    let query = query.trim();
+    if is_glob(query) {
+        let Ok(pattern) = glob::Pattern::new(query) else {
+            return Vec::new();
+        };
+        let options = glob::MatchOptions {
+            case_sensitive: false,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        // Patterns without a separator match on the name alone, like a shell glob in every folder
+        let by_name = !query.contains('/');
+        return index
+            .iter()
+            .filter(|entry| {
+                let target = if by_name {
+                    entry.relative.rsplit('/').next().unwrap_or(&entry.relative)
+                } else {
+                    &entry.relative
+                };
+                pattern.matches_with(target, options)
+            })
+            .take(limit)
+            .collect();
+    }
+
+    let mut scored: Vec<(i64, &IndexEntry)> = index
+        .iter()
+        .filter_map(|entry| fuzzy_score(query, &entry.relative).map(|score| (score, entry)))
+        .collect();
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.relative.cmp(&b.1.relative)));
+    scored.into_iter().take(limit).map(|(_, entry)| entry).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(paths: &[&str]) -> Vec<IndexEntry> {
+        paths
+            .iter()
+            .map(|relative| IndexEntry {
+                path: PathBuf::from(relative),
+                relative: relative.to_string(),
+                is_dir: false,
+            })
+            .collect()
+    }
+
+    fn found(index: &[IndexEntry], query: &str) -> Vec<String> {
+        search(index, query, 10)
+            .into_iter()
+            .map(|entry| entry.relative.clone())
+            .collect()
+    }
+
+    #[test]
+    fn fuzzy_needs_every_character_in_order() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert!(fuzzy_score("mrs", "src/main.rs").is_some());
+        assert!(fuzzy_score("MAIN", "src/main.rs").is_some());
+        assert_eq!(fuzzy_score("srm", "main.rs"), None);
+        assert_eq!(fuzzy_score("mainx", "main.rs"), None);
+    }
+
+    #[test]
+    fn fuzzy_prefers_runs_and_segment_starts() {
+        let run = fuzzy_score("main", "src/main.rs").unwrap();
+        let scattered = fuzzy_score("main", "src/mxaxixn.rs").unwrap();
+        assert!(run > scattered);
+        let segment = fuzzy_score("l", "src/lib.rs").unwrap();
+        let middle = fuzzy_score("l", "src/all.rs").unwrap();
+        assert!(segment > middle);
+        assert!(fuzzy_score("a", "a.rs").unwrap() > fuzzy_score("a", "a/longer/path.rs").unwrap());
+    }
+
+    #[test]
+    fn fuzzy_search_ranks_best_first() {
+        let index = index(&["docs/main_index.md", "src/main.rs", "src/domain.rs", "tests/x.rs"]);
+        assert_eq!(found(&index, "main"), ["src/main.rs", "docs/main_index.md", "src/domain.rs"]);
+        assert_eq!(search(&index, "main", 1).len(), 1);
+    }
+
+    #[test]
+    fn globs_match_names_in_every_folder() {
+        let index = index(&["Cargo.toml", "src/main.rs", "src/lib.RS", "src/deep/mod.rs"]);
+        assert_eq!(found(&index, "*.rs"), ["src/main.rs", "src/lib.RS", "src/deep/mod.rs"]);
+        assert_eq!(found(&index, "m??n.*"), ["src/main.rs"]);
+        assert_eq!(found(&index, "[cl]*"), ["Cargo.toml", "src/lib.RS"]);
+    }
+
+    #[test]
+    fn globs_with_a_separator_match_whole_paths() {
+        let index = index(&["src/main.rs", "src/deep/mod.rs", "main.rs"]);
+        assert_eq!(found(&index, "src/*.rs"), ["src/main.rs"]);
+        assert_eq!(found(&index, "src/**/*.rs"), ["src/main.rs", "src/deep/mod.rs"]);
+        assert!(found(&index, "[").is_empty());
+    }
+}
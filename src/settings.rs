@@ -0,0 +1,147 @@
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use iced::Theme;
+use serde::{Deserialize, Serialize};
+
+use crate::filter::KindFilter;
+use crate::history::Retention;
+use crate::keymap::Action;
+use crate::themes::ThemeSet;
+use crate::Error;
+
+// Everything remembered between runs, stored as settings.toml in the config folder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserSettings {
+    pub theme: String,
+    pub last_folder: Option<PathBuf>,
+    pub tabs: Vec<PathBuf>,
+    pub active_tab: Option<usize>,
+    pub window_width: f32,
+    pub window_height: f32,
+    pub show_hidden: bool,
+    pub kind_filter: String,
+    pub compress_level: u8,
+    pub verify_copies: bool,
+    pub follow_links: bool,
+    pub history_max_versions: usize,
+    pub history_max_days: u64,
+    pub auto_save_on_focus_loss: bool,
+    // Seconds between saves of every unsaved tab, 0 turns it off
+    pub auto_save_interval: u64,
+    pub recent_commands: Vec<String>,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::Dark.to_string(),
+            last_folder: None,
+            tabs: Vec::new(),
+            active_tab: None,
+            window_width: 1024.0,
+            window_height: 768.0,
+            show_hidden: false,
+            kind_filter: KindFilter::All.to_string(),
+            compress_level: 6,
+            verify_copies: false,
+            follow_links: false,
+            history_max_versions: 50,
+            history_max_days: 30,
+            auto_save_on_focus_loss: false,
+            auto_save_interval: 0,
+            recent_commands: Vec::new(),
+        }
+    }
+}
+
+impl UserSettings {
+    pub fn theme(&self, custom: &ThemeSet) -> Option<Theme> {
+        Theme::ALL
+            .iter()
+            .find(|theme| theme.to_string() == self.theme)
+            .or_else(|| custom.find(&self.theme).map(|custom| &custom.theme))
+            .cloned()
+    }
+
+    pub fn recent_actions(&self) -> Vec<Action> {
+        self.recent_commands
+            .iter()
+            .filter_map(|name| Action::from_name(name))
+            .collect()
+    }
+
+    pub fn retention(&self) -> Retention {
+        Retention {
+            max_versions: self.history_max_versions.max(1),
+            max_days: self.history_max_days,
+        }
+    }
+
+    pub fn kind_filter(&self) -> KindFilter {
+        KindFilter::ALL
+            .into_iter()
+            .find(|kind| kind.to_string() == self.kind_filter)
+            .unwrap_or(KindFilter::All)
+    }
+}
+
+// $XDG_CONFIG_HOME/rust-vfs on Linux, the platform config folder elsewhere
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rust-vfs"))
+}
+
+fn settings_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("settings.toml"))
+}
+
+// Read once at startup, a missing or broken file just gives the defaults
+pub fn load() -> UserSettings {
+    let Some(path) = settings_file() else {
+        return UserSettings::default();
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => toml::from_str(&text).unwrap_or_else(|error| {
+            eprintln!("rust-vfs: ignoring {}: {}", path.display(), error);
+            UserSettings::default()
+        }),
+        Err(_) => UserSettings::default(),
+    }
+}
+
+static SAVES: AtomicU64 = AtomicU64::new(0);
+static WRITTEN: Mutex<u64> = Mutex::new(0);
+
+// Saves are numbered here, when they are asked for, and written one at a time. One that
+// gets its turn after a newer save was written is dropped, so an older state never wins
+pub fn save(settings: UserSettings) -> impl Future<Output = Result<(), Error>> {
+    let number = SAVES.fetch_add(1, Ordering::Relaxed) + 1;
+    write(settings, number)
+}
+
+async fn write(settings: UserSettings, number: u64) -> Result<(), Error> {
+    let path = settings_file().ok_or(Error::IOErr(io::ErrorKind::NotFound))?;
+    let text =
+        toml::to_string_pretty(&settings).map_err(|_| Error::IOErr(io::ErrorKind::InvalidData))?;
+    tokio::task::spawn_blocking(move || {
+        let mut written = WRITTEN.lock().unwrap_or_else(PoisonError::into_inner);
+        if *written > number {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension(format!("toml.{}", std::process::id()));
+        std::fs::write(&temp, text)?;
+        std::fs::rename(&temp, &path)?;
+        *written = number;
+        Ok(())
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
@@ -0,0 +1,514 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{File, FileTimes};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::checksum::{self, HashAlgorithm};
+use crate::{archive, links, set_times, settings, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+// One entry of the tree, its path relative to the root with / between folders. A file's
+// content is stored once under its BLAKE3 hash, however many snapshots have it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub path: String,
+    pub kind: EntryKind,
+    #[serde(default)]
+    pub size: u64,
+    pub hash: Option<String>,
+    pub target: Option<PathBuf>,
+    pub mode: Option<u32>,
+    pub modified: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Manifest {
+    root: PathBuf,
+    created: u64,
+    entries: Vec<SnapshotEntry>,
+}
+
+// A snapshot in the list, its entries are only read when it is compared or restored
+#[derive(Debug, Clone)]
+pub struct SnapshotInfo {
+    pub manifest: PathBuf,
+    pub created: SystemTime,
+    pub files: usize,
+    pub size: u64,
+}
+
+impl SnapshotInfo {
+    pub fn label(&self) -> String {
+        archive::time_label(self.created)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    // Only in the live tree
+    Added,
+    // Only in the snapshot
+    Removed,
+    Changed,
+}
+
+// A folder that was added or removed, or became a file, is listed once, not with
+// everything inside it
+#[derive(Debug, Clone)]
+pub struct SnapshotChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub is_dir: bool,
+}
+
+// The Snapshots page for one folder. `compared` is the snapshot the changes are against
+#[derive(Debug)]
+pub struct Snapshots {
+    pub root: PathBuf,
+    pub running: bool,
+    pub list: Vec<SnapshotInfo>,
+    pub compared: Option<usize>,
+    pub changes: Vec<SnapshotChange>,
+}
+
+impl Snapshots {
+    pub fn compared_manifest(&self) -> Option<PathBuf> {
+        self.compared
+            .and_then(|index| self.list.get(index))
+            .map(|info| info.manifest.clone())
+    }
+}
+
+fn store() -> Option<PathBuf> {
+    settings::config_dir().map(|dir| dir.join("snapshots"))
+}
+
+// Taking, restoring and deleting snapshots hold this lock on the whole store until they are
+// done, so a delete never collects the objects of a snapshot still being taken. It is a lock
+// on a file, so it also keeps two running copies of the app apart
+fn lock(store: &Path) -> io::Result<File> {
+    std::fs::create_dir_all(store)?;
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(store.join("lock"))?;
+    file.lock()?;
+    Ok(file)
+}
+
+// A new object is hashed from the bytes as they are copied, so a file that changes meanwhile
+// is still stored under the hash of what was stored. It is copied to a temporary file in the
+// store first, so a half-copied one is never used
+fn store_object(store: &Path, path: &Path) -> io::Result<String> {
+    let hash = checksum::hash_file(path, HashAlgorithm::Blake3)?;
+    if object(store, &hash).exists() {
+        return Ok(hash);
+    }
+    let temp = store.join(format!(".object-{}", uuid::Uuid::new_v4()));
+    let stored = copy_hashed(path, &temp).and_then(|hash| {
+        let object = object(store, &hash);
+        std::fs::create_dir_all(object.parent().unwrap_or(store))?;
+        std::fs::rename(&temp, &object)?;
+        Ok(hash)
+    });
+    if stored.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    stored
+}
+
+fn copy_hashed(from: &Path, to: &Path) -> io::Result<String> {
+    let mut source = File::open(from)?;
+    let mut target = File::create_new(to)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        target.write_all(&buffer[..read])?;
+    }
+    target.sync_all()?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+fn object(store: &Path, hash: &str) -> PathBuf {
+    store.join("objects").join(&hash[..2]).join(&hash[2..])
+}
+
+// The manifests of one folder live in a folder named after a hash of its path
+fn manifests(store: &Path, root: &Path) -> PathBuf {
+    let key = root.to_string_lossy();
+    store.join(format!("{:016x}", xxh3_64(key.as_bytes())))
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> Result<T, Error> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+        .map_err(|error| Error::IOErr(error.kind()))
+}
+
+// Symlinks are kept as links and never followed. Sockets, pipes and devices are left out
+fn walk(root: &Path, relative: &str, entries: &mut Vec<(SnapshotEntry, PathBuf)>) -> io::Result<()> {
+    let mut children: Vec<_> = std::fs::read_dir(root.join(relative))?.collect::<io::Result<_>>()?;
+    children.sort_by_key(|entry| entry.file_name());
+    for child in children {
+        let name = child.file_name().to_string_lossy().into_owned();
+        let path = if relative.is_empty() {
+            name
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        let meta = std::fs::symlink_metadata(child.path())?;
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(meta.permissions().mode())
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        let (kind, target) = if meta.file_type().is_symlink() {
+            (EntryKind::Symlink, Some(std::fs::read_link(child.path())?))
+        } else if meta.is_dir() {
+            (EntryKind::Dir, None)
+        } else if meta.is_file() {
+            (EntryKind::File, None)
+        } else {
+            continue;
+        };
+        let entry = SnapshotEntry {
+            path: path.clone(),
+            kind,
+            size: if kind == EntryKind::File { meta.len() } else { 0 },
+            hash: None,
+            target,
+            mode,
+            modified: meta.modified().ok().map(millis),
+        };
+        entries.push((entry, child.path()));
+        if kind == EntryKind::Dir {
+            walk(root, &path, entries)?;
+        }
+    }
+    Ok(())
+}
+
+// Files already in the store from an earlier snapshot aren't copied again
+pub async fn take(root: PathBuf) -> Result<SnapshotInfo, Error> {
+    blocking(move || {
+        let store = store().ok_or(io::ErrorKind::NotFound)?;
+        let _lock = lock(&store)?;
+        let mut walked = Vec::new();
+        walk(&root, "", &mut walked)?;
+        let mut entries = Vec::with_capacity(walked.len());
+        for (mut entry, path) in walked {
+            if entry.kind == EntryKind::File {
+                entry.hash = Some(store_object(&store, &path)?);
+            }
+            entries.push(entry);
+        }
+        let created = SystemTime::now();
+        let manifest = Manifest {
+            root: root.clone(),
+            created: millis(created),
+            entries,
+        };
+        let folder = manifests(&store, &root);
+        std::fs::create_dir_all(&folder)?;
+        let path = folder.join(format!("{}.toml", manifest.created));
+        let text = toml::to_string(&manifest).map_err(|_| io::ErrorKind::InvalidData)?;
+        std::fs::write(&path, text)?;
+        Ok(info(path, &manifest))
+    })
+    .await
+}
+
+fn info(path: PathBuf, manifest: &Manifest) -> SnapshotInfo {
+    let files = manifest
+        .entries
+        .iter()
+        .filter(|entry| entry.kind == EntryKind::File);
+    SnapshotInfo {
+        manifest: path,
+        created: UNIX_EPOCH + Duration::from_millis(manifest.created),
+        files: files.clone().count(),
+        size: files.map(|entry| entry.size).sum(),
+    }
+}
+
+fn read_manifest(path: &Path) -> io::Result<Manifest> {
+    toml::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))
+}
+
+// Newest first, manifests that can't be read are left out
+pub async fn list(root: PathBuf) -> Result<Vec<SnapshotInfo>, Error> {
+    blocking(move || {
+        let Some(store) = store() else {
+            return Ok(Vec::new());
+        };
+        let folder = manifests(&store, &root);
+        if !folder.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut list = Vec::new();
+        for entry in std::fs::read_dir(folder)? {
+            let path = entry?.path();
+            if let Ok(manifest) = read_manifest(&path) {
+                list.push(info(path, &manifest));
+            }
+        }
+        list.sort_by_key(|info| std::cmp::Reverse(info.created));
+        Ok(list)
+    })
+    .await
+}
+
+// Files of the same size and modification time are taken as unchanged, otherwise they
+// are hashed
+pub async fn diff(manifest: PathBuf, root: PathBuf) -> Result<Vec<SnapshotChange>, Error> {
+    blocking(move || {
+        let snapshot = read_manifest(&manifest)?;
+        let mut walked = Vec::new();
+        walk(&root, "", &mut walked)?;
+        let live: BTreeMap<String, (SnapshotEntry, PathBuf)> = walked
+            .into_iter()
+            .map(|(entry, path)| (entry.path.clone(), (entry, path)))
+            .collect();
+        let saved: BTreeMap<&str, &SnapshotEntry> = snapshot
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+
+        let mut changes = Vec::new();
+        for entry in &snapshot.entries {
+            let kind = match live.get(&entry.path) {
+                None => Some(ChangeKind::Removed),
+                Some((now, path)) if changed(entry, now, path)? => Some(ChangeKind::Changed),
+                Some(_) => None,
+            };
+            if let Some(kind) = kind {
+                changes.push(SnapshotChange {
+                    path: entry.path.clone(),
+                    kind,
+                    is_dir: entry.kind == EntryKind::Dir,
+                });
+            }
+        }
+        for (path, (entry, _)) in &live {
+            if !saved.contains_key(path.as_str()) {
+                changes.push(SnapshotChange {
+                    path: path.clone(),
+                    kind: ChangeKind::Added,
+                    is_dir: entry.kind == EntryKind::Dir,
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+        // Restoring a change restores everything under it too
+        let mut listed: HashSet<String> = HashSet::new();
+        changes.retain(|change| {
+            let inside = ancestors(&change.path).any(|folder| listed.contains(folder));
+            if !inside {
+                listed.insert(change.path.clone());
+            }
+            !inside
+        });
+        Ok(changes)
+    })
+    .await
+}
+
+fn ancestors(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(move |(index, _)| &path[..index])
+}
+
+fn changed(saved: &SnapshotEntry, now: &SnapshotEntry, path: &Path) -> io::Result<bool> {
+    if saved.kind != now.kind {
+        return Ok(true);
+    }
+    Ok(match saved.kind {
+        EntryKind::Dir => false,
+        EntryKind::Symlink => saved.target != now.target,
+        EntryKind::File if saved.size != now.size => true,
+        EntryKind::File if saved.modified == now.modified => false,
+        EntryKind::File => {
+            saved.hash.as_deref() != Some(&checksum::hash_file(path, HashAlgorithm::Blake3)?)
+        }
+    })
+}
+
+// Puts the given changes back the way the snapshot has them. What was added since goes to
+// the system trash, as does anything in the way of an entry of another kind. Files are
+// written next to their place first and renamed over it
+pub async fn restore(
+    manifest: PathBuf,
+    root: PathBuf,
+    changes: Vec<SnapshotChange>,
+) -> Result<usize, Error> {
+    blocking(move || {
+        let store = store().ok_or(io::ErrorKind::NotFound)?;
+        let _lock = lock(&store)?;
+        let snapshot = read_manifest(&manifest)?;
+        let mut trashed = Vec::new();
+        let mut restored: Vec<&SnapshotEntry> = Vec::new();
+        for change in &changes {
+            let live = root.join(&change.path);
+            let live_kind = std::fs::symlink_metadata(&live).ok().map(|meta| {
+                if meta.file_type().is_symlink() {
+                    EntryKind::Symlink
+                } else if meta.is_dir() {
+                    EntryKind::Dir
+                } else {
+                    EntryKind::File
+                }
+            });
+            let saved_kind = snapshot
+                .entries
+                .iter()
+                .find(|entry| entry.path == change.path)
+                .map(|entry| entry.kind);
+            if live_kind.is_some() && (saved_kind.is_none() || live_kind != saved_kind) {
+                trashed.push(live);
+            }
+            restored.extend(snapshot.entries.iter().filter(|entry| {
+                entry.path == change.path
+                    || entry.path.starts_with(&format!("{}/", change.path))
+            }));
+        }
+        if !trashed.is_empty() {
+            trash::delete_all(&trashed).map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+        }
+
+        restored.sort_by(|a, b| a.path.cmp(&b.path));
+        restored.dedup_by(|a, b| a.path == b.path);
+        let mut folders = Vec::new();
+        for entry in &restored {
+            let path = root.join(&entry.path);
+            match entry.kind {
+                EntryKind::Dir => {
+                    std::fs::create_dir_all(&path)?;
+                    folders.push((path, *entry));
+                }
+                EntryKind::Symlink => {
+                    if links::is_symlink(&path) {
+                        std::fs::remove_file(&path)?;
+                    }
+                    if let Some(target) = &entry.target {
+                        symlink(target, &path)?;
+                    }
+                }
+                EntryKind::File => {
+                    let hash = entry.hash.as_deref().ok_or(io::ErrorKind::InvalidData)?;
+                    let folder = path.parent().unwrap_or(&root);
+                    std::fs::create_dir_all(folder)?;
+                    let temp = folder.join(format!(".restore-{}", uuid::Uuid::new_v4()));
+                    let written = std::fs::copy(object(&store, hash), &temp)
+                        .and_then(|_| set_metadata(&temp, entry))
+                        .and_then(|()| std::fs::rename(&temp, &path));
+                    if let Err(error) = written {
+                        let _ = std::fs::remove_file(&temp);
+                        return Err(error);
+                    }
+                }
+            }
+        }
+        // Deepest first, filling a folder changes its modification time
+        for (path, entry) in folders.iter().rev() {
+            set_metadata(path, entry)?;
+        }
+        Ok(restored.len() + changes.iter().filter(|change| change.kind == ChangeKind::Added).count())
+    })
+    .await
+}
+
+fn set_metadata(path: &Path, entry: &SnapshotEntry) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = entry.mode {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    if let Some(modified) = entry.modified {
+        let times = FileTimes::new().set_modified(UNIX_EPOCH + Duration::from_millis(modified));
+        set_times(path, times)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    let points_to_dir = link
+        .parent()
+        .map_or_else(|| target.to_path_buf(), |folder| folder.join(target))
+        .is_dir();
+    if points_to_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+// Stored files no other snapshot uses are deleted with it
+pub async fn delete(manifest: PathBuf) -> Result<(), Error> {
+    blocking(move || {
+        let store = store().ok_or(io::ErrorKind::NotFound)?;
+        let _lock = lock(&store)?;
+        std::fs::remove_file(&manifest)?;
+        let mut used = HashSet::new();
+        for folder in std::fs::read_dir(&store)? {
+            let folder = folder?;
+            if folder.file_name() == "objects" || !folder.file_type()?.is_dir() {
+                continue;
+            }
+            for path in std::fs::read_dir(folder.path())? {
+                let manifest = read_manifest(&path?.path())?;
+                used.extend(manifest.entries.into_iter().filter_map(|entry| entry.hash));
+            }
+        }
+        let objects = store.join("objects");
+        if !objects.is_dir() {
+            return Ok(());
+        }
+        for prefix in std::fs::read_dir(&objects)? {
+            let prefix = prefix?;
+            let start = prefix.file_name().to_string_lossy().into_owned();
+            for object in std::fs::read_dir(prefix.path())? {
+                let object = object?;
+                let hash = format!("{}{}", start, object.file_name().to_string_lossy());
+                if !used.contains(&hash) {
+                    std::fs::remove_file(object.path())?;
+                }
+            }
+        }
+        Ok(())
+    })
+    .await
+}
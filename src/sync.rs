@@ -0,0 +1,522 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::listing::Side;
+use crate::{copy_file, links, settings, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    Mirror,
+    TwoWay,
+}
+
+impl SyncMode {
+    pub const ALL: [SyncMode; 2] = [SyncMode::Mirror, SyncMode::TwoWay];
+}
+
+impl fmt::Display for SyncMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncMode::Mirror => write!(f, "Mirror left to right"),
+            SyncMode::TwoWay => write!(f, "Two-way"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncAction {
+    // Copy from this side to the other one
+    Copy(Side),
+    // Delete on this side
+    Delete(Side),
+    // Changed on both sides since the last sync, nothing happens until it is resolved
+    Conflict,
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncAction::Copy(Side::Left) => write!(f, "copy →"),
+            SyncAction::Copy(Side::Right) => write!(f, "← copy"),
+            SyncAction::Delete(Side::Left) => write!(f, "delete left"),
+            SyncAction::Delete(Side::Right) => write!(f, "delete right"),
+            SyncAction::Conflict => write!(f, "conflict"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncOp {
+    pub relative: String,
+    pub is_dir: bool,
+    pub action: SyncAction,
+}
+
+#[derive(Debug)]
+pub struct SyncJob {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    pub mode: SyncMode,
+    pub delete_extras: bool,
+    pub running: bool,
+    pub plan: Vec<SyncOp>,
+}
+
+// Symlinks are synced as links, never followed. A recreated link gets a new time of its
+// own, so for a link `modified` is a hash of where it points instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    is_dir: bool,
+    is_link: bool,
+    size: u64,
+    modified: u64,
+}
+
+impl Entry {
+    fn same_file(&self, other: &Entry) -> bool {
+        !self.is_dir
+            && !other.is_dir
+            && self.is_link == other.is_link
+            && self.size == other.size
+            && self.modified == other.modified
+    }
+}
+
+// Every file and folder under root by relative path, parents sort before their children
+fn scan(root: &Path) -> io::Result<BTreeMap<String, Entry>> {
+    let mut entries = BTreeMap::new();
+    let mut stack = vec![(root.to_path_buf(), String::new())];
+    while let Some((dir, relative)) = stack.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let meta = std::fs::symlink_metadata(entry.path())?;
+            let is_link = meta.file_type().is_symlink();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let child = if relative.is_empty() {
+                name
+            } else {
+                format!("{}/{}", relative, name)
+            };
+            // Whole seconds, like Compare Folders
+            let modified = if is_link {
+                let target = std::fs::read_link(entry.path())?;
+                xxh3_64(target.to_string_lossy().as_bytes())
+            } else {
+                meta.modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |since| since.as_secs())
+            };
+            if meta.is_dir() {
+                stack.push((entry.path(), child.clone()));
+            }
+            entries.insert(
+                child,
+                Entry {
+                    is_dir: meta.is_dir(),
+                    is_link,
+                    size: if is_link { 0 } else { meta.len() },
+                    modified,
+                },
+            );
+        }
+    }
+    Ok(entries)
+}
+
+// What the files looked like after the last two-way sync of this pair of folders
+type SyncState = BTreeMap<String, (u64, u64)>;
+
+fn state_file(left: &Path, right: &Path) -> Option<PathBuf> {
+    let key = format!("{}\n{}", left.display(), right.display());
+    settings::config_dir().map(|dir| {
+        dir.join("sync")
+            .join(format!("{:016x}.toml", xxh3_64(key.as_bytes())))
+    })
+}
+
+fn load_state(left: &Path, right: &Path) -> SyncState {
+    state_file(left, right)
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| toml::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save_state(left: &Path, right: &Path) -> io::Result<()> {
+    let Some(path) = state_file(left, right) else {
+        return Ok(());
+    };
+    let left_entries = scan(left)?;
+    let right_entries = scan(right)?;
+    let state: SyncState = left_entries
+        .iter()
+        .filter(|(relative, entry)| {
+            right_entries
+                .get(*relative)
+                .is_some_and(|other| entry.same_file(other))
+        })
+        .map(|(relative, entry)| (relative.clone(), (entry.size, entry.modified)))
+        .collect();
+    let text = toml::to_string(&state).map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, text)
+}
+
+// The dry run, works out every copy and delete without touching either folder
+pub async fn plan(
+    left: PathBuf,
+    right: PathBuf,
+    mode: SyncMode,
+    delete_extras: bool,
+) -> Result<Vec<SyncOp>, Error> {
+    tokio::task::spawn_blocking(move || {
+        let left_entries = scan(&left)?;
+        let right_entries = scan(&right)?;
+        Ok(match mode {
+            SyncMode::Mirror => plan_mirror(&left_entries, &right_entries, delete_extras),
+            SyncMode::TwoWay => {
+                plan_two_way(&left_entries, &right_entries, &load_state(&left, &right))
+            }
+        })
+    })
+    .await
+    .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+    .map_err(|error: io::Error| Error::IOErr(error.kind()))
+}
+
+fn plan_mirror(
+    left: &BTreeMap<String, Entry>,
+    right: &BTreeMap<String, Entry>,
+    delete_extras: bool,
+) -> Vec<SyncOp> {
+    let mut plan = Vec::new();
+    // Folders removed from the right, whatever was in them goes with them
+    let mut removed: BTreeSet<&str> = BTreeSet::new();
+    let mut push = |relative: &String, is_dir: bool, action: SyncAction| {
+        plan.push(SyncOp {
+            relative: relative.clone(),
+            is_dir,
+            action,
+        })
+    };
+    for (relative, entry) in left {
+        match right.get(relative) {
+            None => push(relative, entry.is_dir, SyncAction::Copy(Side::Left)),
+            // A file where a folder should be, or the other way round, is replaced
+            Some(other) if other.is_dir != entry.is_dir => {
+                push(relative, other.is_dir, SyncAction::Delete(Side::Right));
+                push(relative, entry.is_dir, SyncAction::Copy(Side::Left));
+                if other.is_dir {
+                    removed.insert(relative);
+                }
+            }
+            Some(other) if !entry.is_dir && !entry.same_file(other) => {
+                push(relative, false, SyncAction::Copy(Side::Left))
+            }
+            Some(_) => {}
+        }
+    }
+    if delete_extras {
+        for (relative, entry) in right {
+            let inside = ancestors(relative).any(|folder| removed.contains(folder));
+            if inside || left.contains_key(relative) {
+                continue;
+            }
+            push(relative, entry.is_dir, SyncAction::Delete(Side::Right));
+            if entry.is_dir {
+                removed.insert(relative);
+            }
+        }
+    }
+    plan
+}
+
+fn ancestors(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(move |(index, _)| &path[..index])
+}
+
+// A file that matches the saved state is unchanged on that side. Changes on one side are
+// copied over, a file gone from one side and unchanged on the other was deleted, anything
+// else is a conflict. Only files are synced, folders are created as files need them
+fn plan_two_way(
+    left: &BTreeMap<String, Entry>,
+    right: &BTreeMap<String, Entry>,
+    state: &SyncState,
+) -> Vec<SyncOp> {
+    let files = |entries: &BTreeMap<String, Entry>| -> BTreeMap<String, Entry> {
+        entries
+            .iter()
+            .filter(|(_, entry)| !entry.is_dir)
+            .map(|(relative, entry)| (relative.clone(), *entry))
+            .collect()
+    };
+    let (left, right) = (files(left), files(right));
+    let names: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+
+    let mut plan = Vec::new();
+    for relative in names {
+        let synced = state.get(relative);
+        let changed = |entry: &Entry| synced != Some(&(entry.size, entry.modified));
+        let action = match (left.get(relative), right.get(relative)) {
+            (Some(l), Some(r)) if l.same_file(r) => continue,
+            (Some(l), Some(r)) => match (changed(l), changed(r)) {
+                (true, false) => SyncAction::Copy(Side::Left),
+                (false, true) => SyncAction::Copy(Side::Right),
+                _ => SyncAction::Conflict,
+            },
+            (Some(_), None) if synced.is_none() => SyncAction::Copy(Side::Left),
+            (Some(l), None) if changed(l) => SyncAction::Conflict,
+            (Some(_), None) => SyncAction::Delete(Side::Left),
+            (None, Some(_)) if synced.is_none() => SyncAction::Copy(Side::Right),
+            (None, Some(r)) if changed(r) => SyncAction::Conflict,
+            (None, Some(_)) => SyncAction::Delete(Side::Right),
+            (None, None) => continue,
+        };
+        plan.push(SyncOp {
+            relative: relative.clone(),
+            is_dir: false,
+            action,
+        });
+    }
+    plan
+}
+
+// Runs the plan in order and returns how many entries were written or removed.
+// Conflicts are skipped, copy_file keeps the source modification time so the next dry run
+// sees the copies as equal
+pub async fn apply(
+    left: PathBuf,
+    right: PathBuf,
+    mode: SyncMode,
+    plan: Vec<SyncOp>,
+) -> Result<usize, Error> {
+    let root = |side: Side| match side {
+        Side::Left => &left,
+        Side::Right => &right,
+    };
+    let mut done = 0;
+    for op in &plan {
+        match op.action {
+            SyncAction::Conflict => continue,
+            SyncAction::Copy(from) => {
+                let source = root(from).join(&op.relative);
+                let target = root(from.other()).join(&op.relative);
+                if op.is_dir {
+                    tokio::fs::create_dir_all(&target)
+                        .await
+                        .map_err(|error| Error::IOErr(error.kind()))?;
+                } else {
+                    let folder = target.parent().unwrap_or(root(from.other())).to_path_buf();
+                    tokio::fs::create_dir_all(&folder)
+                        .await
+                        .map_err(|error| Error::IOErr(error.kind()))?;
+                    // Whatever is there is replaced, never written through if it is a link
+                    let replaced = tokio::fs::symlink_metadata(&target)
+                        .await
+                        .is_ok_and(|meta| !meta.is_dir());
+                    if replaced && (links::is_symlink(&source) || links::is_symlink(&target)) {
+                        tokio::fs::remove_file(&target)
+                            .await
+                            .map_err(|error| Error::IOErr(error.kind()))?;
+                    }
+                    if links::is_symlink(&source) {
+                        links::copy_symlink(&source, &target).await?;
+                    } else {
+                        copy_file(&source, &folder).await?;
+                    }
+                }
+            }
+            SyncAction::Delete(side) => {
+                let path = root(side).join(&op.relative);
+                let removed = if op.is_dir {
+                    tokio::fs::remove_dir_all(&path).await
+                } else {
+                    tokio::fs::remove_file(&path).await
+                };
+                match removed {
+                    Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                        return Err(Error::IOErr(error.kind()));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        done += 1;
+    }
+    if mode == SyncMode::TwoWay {
+        tokio::task::spawn_blocking(move || save_state(&left, &right))
+            .await
+            .map_err(|_| Error::IOErr(io::ErrorKind::Interrupted))?
+            .map_err(|error| Error::IOErr(error.kind()))?;
+    }
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(size: u64, modified: u64) -> Entry {
+        Entry {
+            is_dir: false,
+            is_link: false,
+            size,
+            modified,
+        }
+    }
+
+    fn folder() -> Entry {
+        Entry {
+            is_dir: true,
+            is_link: false,
+            size: 0,
+            modified: 0,
+        }
+    }
+
+    fn entries(list: &[(&str, Entry)]) -> BTreeMap<String, Entry> {
+        list.iter()
+            .map(|(relative, entry)| (relative.to_string(), *entry))
+            .collect()
+    }
+
+    fn actions(plan: &[SyncOp]) -> Vec<(&str, SyncAction)> {
+        plan.iter()
+            .map(|op| (op.relative.as_str(), op.action))
+            .collect()
+    }
+
+    #[test]
+    fn mirror_copies_new_and_changed_files() {
+        let left = entries(&[
+            ("a", folder()),
+            ("a/x", file(1, 10)),
+            ("same", file(2, 20)),
+            ("changed", file(3, 30)),
+        ]);
+        let right = entries(&[("same", file(2, 20)), ("changed", file(3, 31))]);
+        assert_eq!(
+            actions(&plan_mirror(&left, &right, false)),
+            [
+                ("a", SyncAction::Copy(Side::Left)),
+                ("a/x", SyncAction::Copy(Side::Left)),
+                ("changed", SyncAction::Copy(Side::Left)),
+            ]
+        );
+    }
+
+    #[test]
+    fn mirror_replaces_a_folder_with_a_file() {
+        let left = entries(&[("a", file(1, 10))]);
+        let right = entries(&[("a", folder()), ("a/y", file(2, 20)), ("a/z", folder())]);
+        let plan = plan_mirror(&left, &right, true);
+        assert_eq!(
+            actions(&plan),
+            [
+                ("a", SyncAction::Delete(Side::Right)),
+                ("a", SyncAction::Copy(Side::Left)),
+            ]
+        );
+        assert!(plan[0].is_dir);
+        assert!(!plan[1].is_dir);
+    }
+
+    #[test]
+    fn mirror_replaces_a_file_with_a_folder() {
+        let left = entries(&[("a", folder()), ("a/x", file(1, 10))]);
+        let right = entries(&[("a", file(2, 20))]);
+        assert_eq!(
+            actions(&plan_mirror(&left, &right, true)),
+            [
+                ("a", SyncAction::Delete(Side::Right)),
+                ("a", SyncAction::Copy(Side::Left)),
+                ("a/x", SyncAction::Copy(Side::Left)),
+            ]
+        );
+    }
+
+    #[test]
+    fn mirror_deletes_extras_only_when_asked() {
+        let left = entries(&[("keep", file(1, 10))]);
+        let right = entries(&[
+            ("keep", file(1, 10)),
+            ("old", folder()),
+            ("old.d", folder()),
+            ("old.d/x", file(2, 20)),
+            ("old/y", file(3, 30)),
+            ("stray", file(4, 40)),
+        ]);
+        assert!(plan_mirror(&left, &right, false).is_empty());
+        assert_eq!(
+            actions(&plan_mirror(&left, &right, true)),
+            [
+                ("old", SyncAction::Delete(Side::Right)),
+                ("old.d", SyncAction::Delete(Side::Right)),
+                ("stray", SyncAction::Delete(Side::Right)),
+            ]
+        );
+    }
+
+    #[test]
+    fn two_way_copies_changes_from_either_side() {
+        let state: SyncState = [("a".to_string(), (1, 10)), ("b".to_string(), (2, 20))].into();
+        let left = entries(&[("a", file(1, 11)), ("b", file(2, 20)), ("new", file(5, 50))]);
+        let right = entries(&[("a", file(1, 10)), ("b", file(2, 21))]);
+        assert_eq!(
+            actions(&plan_two_way(&left, &right, &state)),
+            [
+                ("a", SyncAction::Copy(Side::Left)),
+                ("b", SyncAction::Copy(Side::Right)),
+                ("new", SyncAction::Copy(Side::Left)),
+            ]
+        );
+    }
+
+    #[test]
+    fn two_way_propagates_deletes() {
+        let state: SyncState = [("a".to_string(), (1, 10)), ("b".to_string(), (2, 20))].into();
+        let left = entries(&[("b", file(2, 20))]);
+        let right = entries(&[("a", file(1, 10))]);
+        assert_eq!(
+            actions(&plan_two_way(&left, &right, &state)),
+            [
+                ("a", SyncAction::Delete(Side::Right)),
+                ("b", SyncAction::Delete(Side::Left)),
+            ]
+        );
+    }
+
+    #[test]
+    fn two_way_reports_conflicts() {
+        let state: SyncState =
+            [("both".to_string(), (1, 10)), ("gone".to_string(), (2, 20))].into();
+        let left = entries(&[
+            ("both", file(1, 11)),
+            ("gone", file(2, 21)),
+            ("fresh", file(3, 30)),
+        ]);
+        let right = entries(&[("both", file(1, 12)), ("fresh", file(3, 31))]);
+        assert_eq!(
+            actions(&plan_two_way(&left, &right, &state)),
+            [
+                ("both", SyncAction::Conflict),
+                ("fresh", SyncAction::Conflict),
+                ("gone", SyncAction::Conflict),
+            ]
+        );
+    }
+
+    #[test]
+    fn two_way_leaves_equal_files_and_folders_alone() {
+        let left = entries(&[("a", folder()), ("a/x", file(1, 10))]);
+        let right = entries(&[("a", folder()), ("a/x", file(1, 10)), ("b", folder())]);
+        assert!(plan_two_way(&left, &right, &SyncState::new()).is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use iced::widget::{button, row, text, text_editor};
+use iced::Command;
+
+use crate::{read_file_directory, recovery, FileSystem, Message, Mode};
+
+// A file opened in the editor. The active tab's text lives in FileSystem::file_content,
+// the others keep theirs here until they are switched to
+#[derive(Debug)]
+pub struct Tab {
+    pub path: PathBuf,
+    pub content: text_editor::Content,
+    // Counts edits, so saves and swap files can tell which text they have
+    pub revision: u64,
+    pub saved: u64,
+    pub swapped: u64,
+}
+
+impl Tab {
+    pub fn new(path: PathBuf, content: text_editor::Content) -> Self {
+        Self {
+            path,
+            content,
+            revision: 0,
+            saved: 0,
+            swapped: 0,
+        }
+    }
+
+    pub fn unsaved(&self) -> bool {
+        self.revision != self.saved
+    }
+}
+
+// The tab helpers of the app, the handlers for tab messages stay in update
+impl FileSystem {
+    // Puts the editor text back into the active tab so another buffer can take the editor
+    pub fn park_active_tab(&mut self) {
+        let content = std::mem::replace(&mut self.file_content, text_editor::Content::new());
+        if let Some(index) = self.active_tab.take() {
+            self.tabs[index].content = content;
+            self.settings_changed = true;
+        }
+    }
+
+    // A file that is already open keeps its unsaved edits and is just switched to
+    pub fn open_tab(&mut self, path: PathBuf, text: &str) {
+        self.park_active_tab();
+        self.settings_changed = true;
+        match self.tabs.iter().position(|tab| tab.path == path) {
+            Some(index) => {
+                self.file_content =
+                    std::mem::replace(&mut self.tabs[index].content, text_editor::Content::new());
+                self.active_tab = Some(index);
+            }
+            None => {
+                self.tabs.push(Tab::new(path, text_editor::Content::new()));
+                self.file_content = text_editor::Content::with_text(text);
+                self.active_tab = Some(self.tabs.len() - 1);
+            }
+        }
+    }
+
+    pub fn switch_tab(&mut self, index: usize) -> Command<Message> {
+        if index >= self.tabs.len() {
+            return Command::none();
+        }
+        if self.active_tab != Some(index) {
+            self.park_active_tab();
+            self.file_content =
+                std::mem::replace(&mut self.tabs[index].content, text_editor::Content::new());
+            self.active_tab = Some(index);
+            self.settings_changed = true;
+        }
+        self.mode = Mode::Opened;
+        let path = self.tabs[index].path.clone();
+        self.dir = Some(path.clone());
+        Command::perform(read_file_directory(path), Message::LoadFileFolder)
+    }
+
+    pub fn close_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() {
+            return;
+        }
+        self.tabs.remove(index);
+        self.settings_changed = true;
+        self.active_tab = match self.active_tab {
+            Some(active) if active == index => {
+                self.file_content = text_editor::Content::new();
+                None
+            }
+            Some(active) if active > index => Some(active - 1),
+            active => active,
+        };
+    }
+
+    pub fn tab_bar(&self) -> iced::widget::Row<'_, Message> {
+        let mut tabs = row![].spacing(5).padding([0, 5]);
+        for (index, tab) in self.tabs.iter().enumerate() {
+            let name = tab.path.file_name().unwrap_or_default().to_string_lossy();
+            let unsaved = if tab.unsaved() { " *" } else { "" };
+            let label = if self.active_tab == Some(index) {
+                format!("[{}{}]", name, unsaved)
+            } else {
+                format!("{}{}", name, unsaved)
+            };
+            tabs = tabs.push(
+                row![
+                    button(text(label)).on_press(Message::SwitchTab(index)),
+                    button("x").on_press(Message::CloseTab(index)),
+                ]
+                .spacing(1),
+            );
+        }
+        tabs
+    }
+
+    // A swap still waiting for a decision, left with Later or kept by closing an unsaved
+    // tab, is never written over or removed before the next start offers it
+    pub fn swap_pending(&self, path: &Path) -> bool {
+        self.swaps.iter().any(|swap| swap.path == path)
+    }
+
+    pub fn remove_swaps(&self, paths: Vec<PathBuf>) -> Command<Message> {
+        let paths = paths
+            .into_iter()
+            .filter(|path| !self.swap_pending(path))
+            .collect();
+        Command::perform(recovery::remove(paths), |_| Message::SwapsRemoved)
+    }
+
+    pub fn buffer_text(&self, index: usize) -> String {
+        if self.active_tab == Some(index) {
+            self.file_content.text()
+        } else {
+            self.tabs[index].content.text()
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use iced::theme::Palette;
+use iced::widget::text_editor::{Appearance, StyleSheet};
+use iced::{Color, Theme};
+use serde::Deserialize;
+
+use crate::settings;
+
+// A palette from the themes folder, the editor colours are optional overrides on top of it
+#[derive(Debug, Clone)]
+pub struct CustomTheme {
+    pub theme: Theme,
+    pub editor: EditorColors,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EditorColors {
+    pub background: Option<Color>,
+    pub text: Option<Color>,
+    pub selection: Option<Color>,
+}
+
+// Every theme file with its modification time, compared on each poll to spot edits
+pub type Stamp = Vec<(PathBuf, Option<SystemTime>)>;
+
+#[derive(Debug, Clone, Default)]
+pub struct ThemeSet {
+    pub stamp: Stamp,
+    pub themes: Vec<CustomTheme>,
+}
+
+impl ThemeSet {
+    pub fn find(&self, name: &str) -> Option<&CustomTheme> {
+        self.themes
+            .iter()
+            .find(|custom| custom.theme.to_string() == name)
+    }
+
+    // The built-in themes followed by the custom ones, for the theme PickList
+    pub fn all(&self) -> Vec<Theme> {
+        Theme::ALL
+            .iter()
+            .cloned()
+            .chain(self.themes.iter().map(|custom| custom.theme.clone()))
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct ThemeFile {
+    name: Option<String>,
+    background: String,
+    text: String,
+    primary: String,
+    success: String,
+    danger: String,
+    #[serde(default)]
+    editor: EditorFile,
+}
+
+#[derive(Deserialize, Default)]
+struct EditorFile {
+    background: Option<String>,
+    text: Option<String>,
+    selection: Option<String>,
+}
+
+pub fn themes_dir() -> Option<PathBuf> {
+    settings::config_dir().map(|dir| dir.join("themes"))
+}
+
+fn stamp(dir: &Path) -> Stamp {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut stamp: Stamp = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("toml" | "json")
+            )
+        })
+        .map(|path| {
+            let modified = std::fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .ok();
+            (path, modified)
+        })
+        .collect();
+    stamp.sort();
+    stamp
+}
+
+// Broken files are reported and skipped so one typo doesn't hide the other themes
+pub fn load() -> ThemeSet {
+    let Some(dir) = themes_dir() else {
+        return ThemeSet::default();
+    };
+    let stamp = stamp(&dir);
+    let mut themes: Vec<CustomTheme> = Vec::new();
+    for (path, _) in &stamp {
+        match load_file(path) {
+            Ok(custom) => {
+                let name = custom.theme.to_string();
+                let taken = Theme::ALL.iter().any(|theme| theme.to_string() == name)
+                    || themes.iter().any(|other| other.theme.to_string() == name);
+                if taken {
+                    eprintln!(
+                        "rust-vfs: ignoring {}: theme {} already exists",
+                        path.display(),
+                        name
+                    );
+                } else {
+                    themes.push(custom);
+                }
+            }
+            Err(message) => eprintln!("rust-vfs: ignoring {}: {}", path.display(), message),
+        }
+    }
+    ThemeSet { stamp, themes }
+}
+
+// Polled from a timer, only reads the files again when one was added, removed or edited
+pub async fn reload(previous: Stamp) -> Option<ThemeSet> {
+    tokio::task::spawn_blocking(move || {
+        let dir = themes_dir()?;
+        if stamp(&dir) == previous {
+            return None;
+        }
+        Some(load())
+    })
+    .await
+    .unwrap_or(None)
+}
+
+fn load_file(path: &Path) -> Result<CustomTheme, String> {
+    let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let file: ThemeFile = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&text).map_err(|error| error.to_string())?
+    } else {
+        toml::from_str(&text).map_err(|error| error.to_string())?
+    };
+    let name = file.name.unwrap_or_else(|| {
+        path.file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    });
+    let palette = Palette {
+        background: parse_color(&file.background)?,
+        text: parse_color(&file.text)?,
+        primary: parse_color(&file.primary)?,
+        success: parse_color(&file.success)?,
+        danger: parse_color(&file.danger)?,
+    };
+    let optional = |value: Option<String>| value.as_deref().map(parse_color).transpose();
+    let editor = EditorColors {
+        background: optional(file.editor.background)?,
+        text: optional(file.editor.text)?,
+        selection: optional(file.editor.selection)?,
+    };
+    Ok(CustomTheme {
+        theme: Theme::custom(name, palette),
+        editor,
+    })
+}
+
+// "#rrggbb" or "#rrggbbaa", the leading # is optional
+fn parse_color(value: &str) -> Result<Color, String> {
+    let hex = value.trim().trim_start_matches('#');
+    let invalid = || format!("invalid colour {:?}, expected #rrggbb or #rrggbbaa", value);
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let channel =
+        |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| invalid());
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Ok(Color::from_rgba8(
+        channel(0)?,
+        channel(2)?,
+        channel(4)?,
+        f32::from(alpha) / 255.0,
+    ))
+}
+
+// Starts from the theme's own editor style and swaps in whichever colours the file set
+pub struct EditorStyle(pub EditorColors);
+
+impl EditorStyle {
+    fn apply(&self, mut appearance: Appearance) -> Appearance {
+        if let Some(background) = self.0.background {
+            appearance.background = background.into();
+        }
+        appearance
+    }
+}
+
+impl StyleSheet for EditorStyle {
+    type Style = Theme;
+
+    fn active(&self, theme: &Theme) -> Appearance {
+        self.apply(theme.active(&Default::default()))
+    }
+
+    fn focused(&self, theme: &Theme) -> Appearance {
+        self.apply(theme.focused(&Default::default()))
+    }
+
+    fn hovered(&self, theme: &Theme) -> Appearance {
+        self.apply(theme.hovered(&Default::default()))
+    }
+
+    fn disabled(&self, theme: &Theme) -> Appearance {
+        self.apply(theme.disabled(&Default::default()))
+    }
+
+    fn placeholder_color(&self, theme: &Theme) -> Color {
+        theme.placeholder_color(&Default::default())
+    }
+
+    fn value_color(&self, theme: &Theme) -> Color {
+        self.0
+            .text
+            .unwrap_or_else(|| theme.value_color(&Default::default()))
+    }
+
+    fn disabled_color(&self, theme: &Theme) -> Color {
+        theme.disabled_color(&Default::default())
+    }
+
+    fn selection_color(&self, theme: &Theme) -> Color {
+        self.0
+            .selection
+            .unwrap_or_else(|| theme.selection_color(&Default::default()))
+    }
+}